@@ -1,11 +1,18 @@
 pub mod parsing {
     use std::{
-        collections::HashMap,
-        fs::{File, OpenOptions},
+        borrow::Cow,
+        collections::{HashMap, HashSet},
+        fmt,
+        fs::File,
         io::{prelude::*, BufReader},
         path::Path,
+        time::Duration,
     };
 
+    use regex::{Captures, Regex};
+
+    use crate::roles::{Level, RoleRef, LEVEL_NAMES};
+
     // Scripting stuff
     #[derive(Clone, Copy, PartialEq)]
     pub enum Direction {
@@ -22,13 +29,16 @@ pub mod parsing {
             direction: Direction,
             distance: i32,
         },
+        // Pressed keys and buttons are let go of when the action ends, unless `keep` is set
         Key {
             button: enigo::Key,
             release: bool,
+            keep: bool,
         },
         Click {
             button: enigo::MouseButton,
             release: bool,
+            keep: bool,
         },
         Screenshot,
         Wait(u64),
@@ -37,278 +47,1664 @@ pub mod parsing {
         End,
     }
 
-    #[derive(Clone)]
+    // The default is a nameless action that does nothing and anyone can use
+    #[derive(Clone, Default)]
     pub struct Action {
         pub name: Option<String>,
+        // Other names players can type for it, from `MoveLeft | left | l:`
+        pub aliases: Vec<String>,
         pub instructions: Vec<Token>,
+        // How long until anyone can use this action again, from `[cooldown=...]`
+        pub cooldown: Option<Duration>,
+        // How long the action waits in total, including the actions it calls
+        pub duration: Duration,
+        // How much of a player's budget this action uses up.
+        // Unless set with `[cost=...]`, it's the same as `duration`.
+        pub cost: Duration,
+        // Who can use this action from chat, from `[level=...]` and `[roles=...]`.
+        // With roles, players need one of them (mods don't).
+        pub level: Level,
+        pub roles: Vec<RoleRef>,
+        // Reacting with this on a reaction controller sends the action, from `[emoji=...]`.
+        // Either a Unicode emoji or a custom one written like `<:name:id>`.
+        pub emoji: Option<String>,
+        // Set for actions like `/walk (left|right) (\d+)/:`, which are typed as anything
+        // that matches instead of by name
+        pub pattern: Option<Pattern>,
     }
-    pub fn parse_action_file() -> HashMap<String, Action> {
-        let mut actions: HashMap<String, Action> = HashMap::new();
-        {
-            let file: File = if Path::new("actions.txt").exists() {
-                OpenOptions::new().read(true).open("actions.txt").unwrap()
+
+    // The trigger and lines of a pattern action. The lines are kept as text, since
+    // `$1`, `$2`... can only be filled in once a message matches.
+    #[derive(Clone)]
+    pub struct Pattern {
+        regex: Regex,
+        // Each line before the `end`, with its line number
+        lines: Vec<(usize, String)>,
+        // The line it starts on, since the first pattern in actions.txt that matches wins
+        line: usize,
+        // From `[cost=...]`, otherwise it's worked out for each message
+        cost: Option<Duration>,
+    }
+
+    impl Action {
+        pub fn is_restricted(&self) -> bool {
+            self.level > Level::Player || !self.roles.is_empty()
+        }
+    }
+
+    // Something wrong with a line of actions.txt.
+    // Lines and columns start at 1, like in a text editor.
+    // Line 0 means the file itself couldn't be read.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct ParseError {
+        pub line: usize,
+        pub column: usize,
+        pub token: String,
+        pub message: String,
+        pub suggestion: Option<String>,
+    }
+
+    impl ParseError {
+        fn new(line: usize, column: usize, token: &str, message: String) -> ParseError {
+            ParseError {
+                line,
+                column,
+                token: token.to_string(),
+                message,
+                suggestion: None,
+            }
+        }
+
+        fn suggest(mut self, suggestion: String) -> ParseError {
+            self.suggestion = Some(suggestion);
+            self
+        }
+    }
+
+    impl fmt::Display for ParseError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            // Problems with the whole file don't have a position
+            if self.line == 0 {
+                write!(f, "{} ({})", self.message, self.token)?;
             } else {
-                {
-                    OpenOptions::new()
-                        .write(true)
-                        .create(true)
-                        .open("actions.txt")
-                        .unwrap();
+                write!(
+                    f,
+                    "line {}, column {}: {} (at '{}')",
+                    self.line, self.column, self.message, self.token
+                )?;
+            }
+            if let Some(suggestion) = &self.suggestion {
+                write!(f, "\n  {}", suggestion)?;
+            }
+            Ok(())
+        }
+    }
+
+    const DIRECTION_NAMES: &[&str] = &["up", "down", "left", "right"];
+
+    const MOUSE_BUTTON_NAMES: &[&str] = &["left", "middle", "right"];
+
+    #[rustfmt::skip]
+    const KEY_NAMES: &[&str] = &[
+        "alt", "backspace", "back", "caps_lock", "control", "ctrl", "del", "delete", "down",
+        "end", "esc", "escape", "f1", "f2", "f3", "f4", "f5", "f6", "f7", "f8", "f9", "f10",
+        "f11", "f12", "home", "left", "win", "windows", "meta", "command", "super", "option",
+        "pgdown", "pg_down", "page_down", "pgup", "pg_up", "page_up", "return", "enter", "right",
+        "shift", "space", "tab", "up", "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l",
+        "m", "n", "o", "p", "q", "r", "s", "t", "u", "v", "w", "x", "y", "z", "1", "2", "3", "4",
+        "5", "6", "7", "8", "9", "`", "-", "=", "[", "]", "\\", ";", "'", ",", ".", "/",
+    ];
+
+    const SETTING_NAMES: &[&str] = &["cooldown", "cost", "level", "roles", "emoji"];
+
+    const INSTRUCTION_NAMES: &[&str] = &[
+        "move",
+        "press",
+        "hold",
+        "release",
+        "wait",
+        "type",
+        "screenshot",
+        "end",
+    ];
+
+    pub fn direction_from_name(name: &str) -> Option<Direction> {
+        match name {
+            "up" => Some(Direction::Up),
+            "down" => Some(Direction::Down),
+            "left" => Some(Direction::Left),
+            "right" => Some(Direction::Right),
+            _ => None,
+        }
+    }
+
+    pub fn mouse_button_from_name(name: &str) -> Option<enigo::MouseButton> {
+        match name {
+            "left" => Some(enigo::MouseButton::Left),
+            "middle" => Some(enigo::MouseButton::Middle),
+            "right" => Some(enigo::MouseButton::Right),
+            _ => None,
+        }
+    }
+
+    pub fn key_from_name(name: &str) -> Option<enigo::Key> {
+        let key = match name {
+            "alt" => enigo::Key::Alt,
+            "backspace" | "back" => enigo::Key::Backspace,
+            "caps_lock" => enigo::Key::CapsLock,
+            "control" | "ctrl" => enigo::Key::Control,
+            "del" | "delete" => enigo::Key::Delete,
+            "down" => enigo::Key::DownArrow,
+            "end" => enigo::Key::End,
+            "esc" | "escape" => enigo::Key::Escape,
+            "f1" => enigo::Key::F1,
+            "f10" => enigo::Key::F10,
+            "f11" => enigo::Key::F11,
+            "f12" => enigo::Key::F12,
+            "f2" => enigo::Key::F2,
+            "f3" => enigo::Key::F3,
+            "f4" => enigo::Key::F4,
+            "f5" => enigo::Key::F5,
+            "f6" => enigo::Key::F6,
+            "f7" => enigo::Key::F7,
+            "f8" => enigo::Key::F8,
+            "f9" => enigo::Key::F9,
+            "home" => enigo::Key::Home,
+            "left" => enigo::Key::LeftArrow,
+            "win" | "windows" | "meta" | "command" | "super" => enigo::Key::Meta,
+            "option" => enigo::Key::Option,
+            "pgdown" | "pg_down" | "page_down" => enigo::Key::PageDown,
+            "pgup" | "pg_up" | "page_up" => enigo::Key::PageUp,
+            "return" | "enter" => enigo::Key::Return,
+            "right" => enigo::Key::RightArrow,
+            "shift" => enigo::Key::Shift,
+            "space" => enigo::Key::Space,
+            "tab" => enigo::Key::Tab,
+            "up" => enigo::Key::UpArrow,
+            // Every other key name is a single character on the keyboard
+            _ if KEY_NAMES.contains(&name) => enigo::Key::Layout(name.chars().next()?),
+            _ => return None,
+        };
+        Some(key)
+    }
+
+    // The longest time `parse_duration` accepts. Anything longer is almost certainly a
+    // mistake, and would overflow when added to the current time.
+    pub const MAX_DURATION: Duration = Duration::from_secs(365 * 86400);
+
+    // Reads times like "500ms", "1.5s", "2m", "1h" or "7d", up to a year.
+    // A plain number is in milliseconds, like everywhere else in actions.txt.
+    pub fn parse_duration(text: &str) -> Option<Duration> {
+        let (number, scale) = if let Some(number) = text.strip_suffix("ms") {
+            (number, 0.001)
+        } else if let Some(number) = text.strip_suffix('s') {
+            (number, 1.0)
+        } else if let Some(number) = text.strip_suffix('m') {
+            (number, 60.0)
+        } else if let Some(number) = text.strip_suffix('h') {
+            (number, 3600.0)
+        } else if let Some(number) = text.strip_suffix('d') {
+            (number, 86400.0)
+        } else {
+            (text, 0.001)
+        };
+        let seconds = number.parse::<f64>().ok()? * scale;
+        Duration::try_from_secs_f64(seconds)
+            .ok()
+            .filter(|duration| *duration <= MAX_DURATION)
+    }
+
+    // Plain edit distance, used to guess what a typo was supposed to be.
+    pub fn levenshtein(a: &str, b: &str) -> usize {
+        let b: Vec<char> = b.chars().collect();
+        let mut row: Vec<usize> = (0..=b.len()).collect();
+        for (i, a_char) in a.chars().enumerate() {
+            let mut previous = row[0];
+            row[0] = i + 1;
+            for (j, b_char) in b.iter().enumerate() {
+                let current = row[j + 1];
+                row[j + 1] = if a_char == *b_char {
+                    previous
+                } else {
+                    1 + previous.min(row[j]).min(row[j + 1])
+                };
+                previous = current;
+            }
+        }
+        row[b.len()]
+    }
+
+    fn closest_name<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+        candidates
+            .iter()
+            .map(|candidate| (levenshtein(word, candidate), *candidate))
+            .filter(|(distance, _)| *distance <= 2)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, candidate)| candidate)
+    }
+
+    fn did_you_mean(word: &str, candidates: &[&str]) -> String {
+        match closest_name(word, candidates) {
+            Some(name) => format!("Did you mean '{}'?", name),
+            None => format!("Expected one of: {}", candidates.join(", ")),
+        }
+    }
+
+    // Splits a line into words, dropping comments.
+    // Each word comes with the column it starts at.
+    fn split_words(line: &str) -> Vec<(usize, &str)> {
+        let code = line.split("//").next().unwrap_or("");
+        let mut words = vec![];
+        let mut start: Option<(usize, usize)> = None;
+        for (column, (index, character)) in code.char_indices().enumerate() {
+            if character.is_whitespace() {
+                if let Some((word_column, word_index)) = start.take() {
+                    words.push((word_column + 1, &code[word_index..index]));
                 }
-                println!(
-                    "Create new actions in actions.txt\nSee the GitHub for documentation and examples."
-                );
-                OpenOptions::new().read(true).open("actions.txt").unwrap()
-            };
+            } else if start.is_none() {
+                start = Some((column, index));
+            }
+        }
+        if let Some((word_column, word_index)) = start {
+            words.push((word_column + 1, &code[word_index..]));
+        }
+        words
+    }
 
-            // Check entire file for actions before compiling actions
-            let first_pass = BufReader::new(file);
-            let mut line_num: u64 = 0;
-            for line in first_pass.lines() {
-                if let Ok(the_line) = line {
-                    line_num += 1;
-                    let no_comments = the_line.split("//").next().unwrap().to_string();
+    // Fetches the argument at `index`, or explains what is missing after the last word.
+    fn argument<'a>(
+        words: &[(usize, &'a str)],
+        index: usize,
+        line_num: usize,
+        what: &str,
+        example: &str,
+    ) -> Result<(usize, &'a str), ParseError> {
+        match words.get(index) {
+            Some(word) => Ok(*word),
+            None => {
+                let (column, last) = words[words.len() - 1];
+                Err(ParseError::new(
+                    line_num,
+                    column + last.chars().count(),
+                    last,
+                    format!("Missing {} in '{}' instruction", what, words[0].1),
+                )
+                .suggest(format!("For example: '{}'", example)))
+            }
+        }
+    }
 
-                    let raw_instruction: Vec<&str> =
-                        no_comments.split_whitespace().collect::<Vec<&str>>();
+    // Custom emoji look like `<:name:id>`, or `<a:name:id>` when animated.
+    // Anything else has to have something besides plain ASCII in it, so `emoji=up` is caught.
+    fn is_emoji(value: &str) -> bool {
+        match value
+            .strip_prefix('<')
+            .and_then(|custom| custom.strip_suffix('>'))
+        {
+            Some(custom) => {
+                let parts: Vec<&str> = custom.split(':').collect();
+                parts.len() == 3
+                    && (parts[0].is_empty() || parts[0] == "a")
+                    && !parts[1].is_empty()
+                    && parts[2].parse::<u64>().is_ok()
+            }
+            None => !value.is_ascii(),
+        }
+    }
 
-                    let trimmed_line = raw_instruction.join(" ");
+    // Splits text on `separator`, except inside double quotes.
+    // Each piece comes with how many characters into the text it starts.
+    fn split_unquoted(text: &str, separator: char) -> Vec<(usize, &str)> {
+        let mut pieces = vec![];
+        let mut quoted = false;
+        let mut start = (0, 0);
+        for (offset, (index, character)) in text.char_indices().enumerate() {
+            if character == '"' {
+                quoted = !quoted;
+            } else if character == separator && !quoted {
+                pieces.push((start.0, &text[start.1..index]));
+                start = (offset + 1, index + character.len_utf8());
+            }
+        }
+        pieces.push((start.0, &text[start.1..]));
+        pieces
+    }
 
-                    if trimmed_line == "" {
-                        continue;
-                    }
+    // Takes the quotes off a value like `"Discord Plays Manager"`.
+    // Values without quotes can't have spaces in them.
+    fn unquote(value: &str) -> Option<&str> {
+        match value
+            .strip_prefix('"')
+            .and_then(|quoted| quoted.strip_suffix('"'))
+        {
+            Some(quoted) => Some(quoted),
+            None if value.contains(char::is_whitespace) || value.contains('"') => None,
+            None => Some(value),
+        }
+    }
 
-                    if trimmed_line.ends_with(":") {
-                        let action_name = trimmed_line.split(":").next().unwrap();
-                        actions.insert(
-                            action_name.to_string(),
-                            Action {
-                                name: Some(action_name.to_string()),
-                                instructions: vec![],
-                            },
-                        );
-                        match action_name.split(" ").next().unwrap() {
-                            "move" | "press" | "hold" | "release" | "wait" | "type" | "end" => {
-                                println!("WARNING: action with same name as builtin instruction at line {}", line_num);
-                            }
-                            _ => {}
-                        }
+    // Reads one `name=value` from the brackets of an action's header.
+    // Values can be quoted, so role names can have spaces.
+    fn parse_setting(
+        action: &mut Action,
+        cost: &mut Option<Duration>,
+        column: usize,
+        setting: &str,
+        line_num: usize,
+    ) -> Result<(), ParseError> {
+        let (key, value) = setting.split_once('=').ok_or_else(|| {
+            ParseError::new(
+                line_num,
+                column,
+                setting,
+                "Action setting without a value".to_string(),
+            )
+            .suggest(format!("Settings look like '{}=...'", setting))
+        })?;
+        let value_column = column + key.chars().count() + 1;
+        // Roles are split up first, since each one can be quoted
+        let value = match key {
+            "roles" => value,
+            _ => unquote(value).ok_or_else(|| {
+                ParseError::new(
+                    line_num,
+                    value_column,
+                    value,
+                    format!("Unquoted space or stray quote in '{}' setting", key),
+                )
+                .suggest(format!(
+                    "Values with spaces need quotes, like '{}=\"...\"'",
+                    key
+                ))
+            })?,
+        };
+        match key {
+            "cooldown" => {
+                action.cooldown = Some(parse_duration(value).ok_or_else(|| {
+                    ParseError::new(
+                        line_num,
+                        value_column,
+                        value,
+                        "Invalid time in 'cooldown' setting".to_string(),
+                    )
+                    .suggest("Times look like '500ms', '30s' or '2m'".to_string())
+                })?);
+            }
+            "cost" => {
+                *cost = Some(parse_duration(value).ok_or_else(|| {
+                    ParseError::new(
+                        line_num,
+                        value_column,
+                        value,
+                        "Invalid time in 'cost' setting".to_string(),
+                    )
+                    .suggest("Times look like '500ms', '30s' or '2m'".to_string())
+                })?);
+            }
+            "level" => {
+                action.level = value.parse().map_err(|_| {
+                    ParseError::new(
+                        line_num,
+                        value_column,
+                        value,
+                        "Unknown level in 'level' setting".to_string(),
+                    )
+                    .suggest(did_you_mean(value, &LEVEL_NAMES))
+                })?;
+            }
+            "roles" => {
+                // Several roles are split with '|', like 'roles=Trusted|VIP'.
+                // Names with spaces are quoted, like 'roles="Discord Plays Manager"|VIP'.
+                for (offset, written) in split_unquoted(value, '|') {
+                    let trimmed = written.trim_start();
+                    let role_column =
+                        value_column + offset + written.chars().count() - trimmed.chars().count();
+                    let written = trimmed.trim_end();
+                    let role = unquote(written).ok_or_else(|| {
+                        ParseError::new(
+                            line_num,
+                            role_column,
+                            written,
+                            "Role names with spaces need quotes".to_string(),
+                        )
+                        .suggest(format!(
+                            "Write it as 'roles=\"{}\"'",
+                            written.replace('"', "")
+                        ))
+                    })?;
+                    if role.is_empty() {
+                        return Err(ParseError::new(
+                            line_num,
+                            role_column,
+                            value,
+                            "Empty role in 'roles' setting".to_string(),
+                        )
+                        .suggest("Roles look like 'roles=Trusted|VIP'".to_string()));
                     }
+                    action.roles.push(RoleRef::new(role));
+                }
+            }
+            "emoji" => {
+                if !is_emoji(value) {
+                    return Err(ParseError::new(
+                        line_num,
+                        value_column,
+                        value,
+                        "Invalid emoji in 'emoji' setting".to_string(),
+                    )
+                    .suggest(
+                        "Use the emoji itself, like 'emoji=⬆️', or a custom one like 'emoji=<:jump:123456789012345678>'"
+                            .to_string(),
+                    ));
                 }
+                action.emoji = Some(value.to_string());
+            }
+            _ => {
+                return Err(ParseError::new(
+                    line_num,
+                    column,
+                    key,
+                    "Unknown action setting".to_string(),
+                )
+                .suggest(did_you_mean(key, SETTING_NAMES)))
             }
         }
-        let file: File = OpenOptions::new().read(true).open("actions.txt").unwrap();
-        let reader = BufReader::new(file);
+        Ok(())
+    }
 
-        let mut action: Action = Action {
-            name: None,
-            instructions: vec![],
+    // Reads an action's first line, like `Save [cooldown=60s]:`.
+    // The settings in brackets are optional.
+    // Also returns the cost, if one was set, since the default can only be worked out later.
+    fn parse_header(
+        words: &[(usize, &str)],
+        line_num: usize,
+    ) -> Result<(Action, Option<Duration>), ParseError> {
+        let mut words = words.to_vec();
+        let (column, last) = words.pop().unwrap();
+        let last = last.strip_suffix(':').unwrap_or(last);
+        if !last.is_empty() {
+            words.push((column, last));
+        }
+        if words.first().is_some_and(|(_, word)| word.starts_with('/')) {
+            let (settings_start, name, pattern) = parse_pattern(&words, line_num)?;
+            let mut action = Action {
+                name: Some(name),
+                pattern: Some(pattern),
+                ..Default::default()
+            };
+            let cost = parse_settings(&mut action, &words[settings_start..], line_num)?;
+            // A reaction has nowhere to put `$1`
+            if action.emoji.is_some() {
+                return Err(ParseError::new(
+                    line_num,
+                    words[0].0,
+                    words[0].1,
+                    "Pattern actions can't have an emoji".to_string(),
+                )
+                .suggest("Give the emoji to an action with a name instead".to_string()));
+            }
+            return Ok((action, cost));
+        }
+        let settings_start = words
+            .iter()
+            .position(|(_, word)| word.starts_with('['))
+            .unwrap_or(words.len());
+        // `MoveLeft | left | l` is a name and two aliases, each with the column it starts at
+        let mut names: Vec<(usize, String)> = vec![(column, String::new())];
+        for (word_column, word) in &words[..settings_start] {
+            let mut piece_column = *word_column;
+            for (index, piece) in word.split('|').enumerate() {
+                if index > 0 {
+                    names.push((piece_column, String::new()));
+                }
+                let (start, name) = names.last_mut().unwrap();
+                if !piece.is_empty() {
+                    if name.is_empty() {
+                        *start = piece_column;
+                    } else {
+                        name.push(' ');
+                    }
+                    name.push_str(piece);
+                }
+                piece_column += piece.chars().count() + 1;
+            }
+        }
+        let (name_column, name) = names.remove(0);
+        if name.is_empty() {
+            return Err(ParseError::new(
+                line_num,
+                name_column,
+                ":",
+                "Action without a name".to_string(),
+            )
+            .suggest("Put the action's name before the ':', like 'Jump:'".to_string()));
+        }
+        let mut aliases: Vec<String> = vec![];
+        for (alias_column, alias) in names {
+            if alias.is_empty() {
+                return Err(ParseError::new(
+                    line_num,
+                    alias_column,
+                    "|",
+                    "Empty alias".to_string(),
+                )
+                .suggest("Aliases look like 'MoveLeft | left | l:'".to_string()));
+            }
+            if alias != name && !aliases.contains(&alias) {
+                aliases.push(alias);
+            }
+        }
+        let mut action = Action {
+            name: Some(name),
+            aliases,
+            ..Default::default()
         };
+        let cost = parse_settings(&mut action, &words[settings_start..], line_num)?;
+        Ok((action, cost))
+    }
 
-        let mut line_num: u64 = 0;
-        for line in reader.lines() {
-            // An example of my incredibly sophisticated naming system
-            if let Ok(the_line) = line {
-                line_num += 1;
-                let no_comments = the_line.split("//").next().unwrap().to_string();
+    // Reads the settings in brackets at the end of a header, if there are any.
+    // Returns the cost, if one was set.
+    fn parse_settings(
+        action: &mut Action,
+        words: &[(usize, &str)],
+        line_num: usize,
+    ) -> Result<Option<Duration>, ParseError> {
+        let mut cost = None;
+        if words.is_empty() {
+            return Ok(cost);
+        }
+        let (last_column, last_word) = words[words.len() - 1];
+        if !last_word.ends_with(']') {
+            return Err(ParseError::new(
+                line_num,
+                last_column,
+                last_word,
+                "Missing ']' after the action's settings".to_string(),
+            )
+            .suggest("Settings look like 'Save [cooldown=60s]:'".to_string()));
+        }
+        // Put back together, so quoted values can have spaces in them
+        let start = words[0].0;
+        let mut text = String::new();
+        for (column, word) in words {
+            while start + text.chars().count() < *column {
+                text.push(' ');
+            }
+            text.push_str(word);
+        }
+        if !text.matches('"').count().is_multiple_of(2) {
+            let quote = start + text.chars().take_while(|c| *c != '"').count();
+            return Err(ParseError::new(
+                line_num,
+                quote,
+                "\"",
+                "Missing closing '\"' in the action's settings".to_string(),
+            )
+            .suggest("Quoted values look like 'roles=\"Discord Plays Manager\"'".to_string()));
+        }
+        // Settings always start with the '['
+        let inside = &text[1..];
+        let inside = inside.strip_suffix(']').unwrap_or(inside);
+        for (offset, setting) in split_unquoted(inside, ',') {
+            let trimmed = setting.trim_start();
+            let column = start + 1 + offset + setting.chars().count() - trimmed.chars().count();
+            let trimmed = trimmed.trim_end();
+            if !trimmed.is_empty() {
+                parse_setting(action, &mut cost, column, trimmed, line_num)?;
+            }
+        }
+        Ok(cost)
+    }
 
-                let mut raw_instruction: Vec<&str> =
-                    no_comments.split_whitespace().collect::<Vec<&str>>();
+    // Reads the pattern at the start of a header like `/walk (left|right) (\d+)/:`.
+    // Returns where the settings start, the pattern as written and the pattern itself.
+    fn parse_pattern(
+        words: &[(usize, &str)],
+        line_num: usize,
+    ) -> Result<(usize, String, Pattern), ParseError> {
+        let (column, first) = words[0];
+        // Patterns can have spaces in them, so the last word ending in '/' closes it
+        let end = words
+            .iter()
+            .rposition(|(_, word)| word.ends_with('/'))
+            .filter(|end| *end > 0 || first.len() > 1)
+            .ok_or_else(|| {
+                let (last_column, last) = words[words.len() - 1];
+                ParseError::new(
+                    line_num,
+                    last_column,
+                    last,
+                    "Missing '/' after the pattern".to_string(),
+                )
+                .suggest("Patterns look like '/walk (left|right) (\\d+)/:'".to_string())
+            })?;
+        if let Some((extra_column, extra)) = words.get(end + 1) {
+            if !extra.starts_with('[') {
+                return Err(ParseError::new(
+                    line_num,
+                    *extra_column,
+                    extra,
+                    "Unexpected text after the pattern".to_string(),
+                )
+                .suggest(
+                    "Settings go in brackets, like '/walk (left|right)/ [cooldown=2s]:'"
+                        .to_string(),
+                ));
+            }
+        }
+        let written: Vec<&str> = words[..=end].iter().map(|(_, word)| *word).collect();
+        let written = written.join(" ");
+        let source = &written[1..written.len() - 1];
+        if source.trim().is_empty() {
+            return Err(
+                ParseError::new(line_num, column, &written, "Empty pattern".to_string())
+                    .suggest("Patterns look like '/walk (left|right) (\\d+)/:'".to_string()),
+            );
+        }
+        // The whole message has to match, not just part of it
+        let regex = Regex::new(&format!("^(?:{})$", source)).map_err(|why| {
+            // The last line says what is wrong, the rest points at it
+            let why = why.to_string();
+            let why = why.lines().last().unwrap_or_default();
+            ParseError::new(
+                line_num,
+                column,
+                &written,
+                format!("Invalid pattern: {}", why.trim_start_matches("error: ")),
+            )
+            .suggest("Patterns use the syntax of Rust's regex crate".to_string())
+        })?;
+        let pattern = Pattern {
+            regex,
+            lines: vec![],
+            line: line_num,
+            cost: None,
+        };
+        Ok((end + 1, written, pattern))
+    }
 
-                let trimmed_line = raw_instruction.join(" ");
+    // Each `$1`, `$2`... in some text, and the group it stands for
+    fn placeholders(text: &str) -> Vec<(std::ops::Range<usize>, usize)> {
+        let mut found = vec![];
+        for (start, _) in text.match_indices('$') {
+            let rest = &text[start + 1..];
+            let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            if digits > 0 {
+                let group = rest[..digits].parse().unwrap_or(usize::MAX);
+                found.push((start..start + 1 + digits, group));
+            }
+        }
+        found
+    }
 
-                if trimmed_line == "" {
-                    continue;
+    // Fills in `$1`, `$2`... with what the groups matched. `$0` is everything.
+    fn substitute(text: &str, captures: &Captures) -> String {
+        let mut filled = String::new();
+        let mut last = 0;
+        for (range, group) in placeholders(text) {
+            filled.push_str(&text[last..range.start]);
+            filled.push_str(captures.get(group).map_or("", |found| found.as_str()));
+            last = range.end;
+        }
+        filled.push_str(&text[last..]);
+        filled
+    }
+
+    // Checks a line of a pattern action as far as it can before anything is filled in
+    fn check_pattern_line(
+        words: &[(usize, &str)],
+        line_num: usize,
+        pattern: &Pattern,
+        action_names: &HashMap<String, String>,
+    ) -> Result<(), ParseError> {
+        let groups = pattern.regex.captures_len() - 1;
+        let mut any = false;
+        for (column, word) in words {
+            for (range, group) in placeholders(word) {
+                any = true;
+                if group > groups {
+                    let placeholder = &word[range.clone()];
+                    return Err(ParseError::new(
+                        line_num,
+                        column + word[..range.start].chars().count(),
+                        placeholder,
+                        format!("'{}' isn't a group in the pattern", placeholder),
+                    )
+                    .suggest(match groups {
+                        0 => "Put part of the pattern in brackets, like '(left|right)', to use it as $1".to_string(),
+                        1 => "The pattern has one group, so use $1".to_string(),
+                        _ => format!("The pattern has {} groups, so use $1 to ${}", groups, groups),
+                    }));
+                }
+            }
+        }
+        // Lines without anything to fill in can be checked now
+        if !any {
+            return parse_instruction(words, line_num, action_names, &mut vec![]);
+        }
+        // Otherwise the instruction can be, unless it's filled in too.
+        // A call only needs an action whose name starts with it, like `Jump $1`.
+        let (column, instruction) = words[0];
+        if placeholders(instruction).is_empty()
+            && !INSTRUCTION_NAMES.contains(&instruction)
+            && !action_names
+                .keys()
+                .any(|name| name.split_whitespace().next() == Some(instruction))
+        {
+            let mut candidates: Vec<&str> = INSTRUCTION_NAMES.to_vec();
+            candidates.extend(action_names.keys().map(|name| name.as_str()));
+            return Err(ParseError::new(
+                line_num,
+                column,
+                instruction,
+                "Invalid instruction or unknown action".to_string(),
+            )
+            .suggest(match closest_name(instruction, &candidates) {
+                Some(name) => format!("Did you mean '{}'?", name),
+                None => {
+                    "Actions must be defined somewhere in actions.txt before they can be called"
+                        .to_string()
                 }
+            }));
+        }
+        Ok(())
+    }
 
-                if trimmed_line.ends_with(":") {
-                    action.name =
-                        Some(trimmed_line.split(":").next().unwrap().to_string());
-                    continue;
+    // The action to run for some text. Usually that's the action with that name, but text
+    // like `walk left 500` can match a pattern action instead.
+    pub fn resolve<'a>(
+        text: &str,
+        actions: &'a HashMap<String, Action>,
+    ) -> Option<Cow<'a, Action>> {
+        match actions.get(text) {
+            Some(action) if action.pattern.is_none() => Some(Cow::Borrowed(action)),
+            _ => match_pattern(text, actions)?.ok().map(Cow::Owned),
+        }
+    }
+
+    // The first pattern action in actions.txt that matches the text, with `$1`, `$2`... filled in.
+    // Gives an error if what was filled in isn't a real key, direction or number,
+    // just like it would in actions.txt.
+    pub fn match_pattern(
+        text: &str,
+        actions: &HashMap<String, Action>,
+    ) -> Option<Result<Action, ParseError>> {
+        let mut patterns: Vec<(&Action, &Pattern)> = actions
+            .values()
+            .filter_map(|action| Some((action, action.pattern.as_ref()?)))
+            .collect();
+        patterns.sort_by_key(|(_, pattern)| pattern.line);
+        let (action, pattern, captures) = patterns
+            .into_iter()
+            .find_map(|(action, pattern)| Some((action, pattern, pattern.regex.captures(text)?)))?;
+
+        let mut action_names: HashMap<String, String> = HashMap::new();
+        for (name, other) in actions.iter().filter(|(_, other)| other.pattern.is_none()) {
+            for alias in std::iter::once(name).chain(&other.aliases) {
+                action_names.insert(alias.clone(), name.clone());
+            }
+        }
+        let mut instructions = vec![];
+        for (line_num, line) in &pattern.lines {
+            let line = substitute(line, &captures);
+            let words = split_words(&line);
+            if words.is_empty() {
+                continue;
+            }
+            if let Err(error) =
+                parse_instruction(&words, *line_num, &action_names, &mut instructions)
+            {
+                return Some(Err(error));
+            }
+        }
+        instructions.push(Token::End);
+        // Every other action has been added up already, when it was parsed
+        let duration = wait_of(&instructions, &mut |called| {
+            actions
+                .get(called)
+                .map_or(Duration::ZERO, |called| called.duration)
+        });
+        Some(Ok(Action {
+            instructions,
+            duration,
+            cost: pattern.cost.unwrap_or(duration),
+            pattern: None,
+            ..action.clone()
+        }))
+    }
+
+    // Adds up every wait in an action, including the actions it calls.
+    // `calling` holds the actions already being added up, so an action that
+    // calls itself doesn't go on forever (that is reported by `find_cycle`).
+    // `durations` remembers every action already added up, so an action called
+    // from many places is only added up once.
+    fn total_wait(
+        name: &str,
+        actions: &HashMap<String, Action>,
+        calling: &mut Vec<String>,
+        durations: &mut HashMap<String, Duration>,
+    ) -> Duration {
+        if let Some(duration) = durations.get(name) {
+            return *duration;
+        }
+        let action = match actions.get(name) {
+            Some(action) if !calling.iter().any(|called| called == name) => action,
+            _ => return Duration::ZERO,
+        };
+        calling.push(name.to_string());
+        let total = wait_of(&action.instructions, &mut |called| {
+            total_wait(called, actions, calling, durations)
+        });
+        calling.pop();
+        durations.insert(name.to_string(), total);
+        total
+    }
+
+    // Follows every call an action makes. If one leads back to an action already
+    // being followed, returns the way round, like ["A", "B", "A"].
+    // `checked` holds actions already known not to lead to one.
+    fn find_cycle(
+        name: &str,
+        actions: &HashMap<String, Action>,
+        calling: &mut Vec<String>,
+        checked: &mut HashSet<String>,
+    ) -> Option<Vec<String>> {
+        if let Some(start) = calling.iter().position(|called| called == name) {
+            let mut cycle = calling[start..].to_vec();
+            cycle.push(name.to_string());
+            return Some(cycle);
+        }
+        if checked.contains(name) {
+            return None;
+        }
+        let action = actions.get(name)?;
+        calling.push(name.to_string());
+        for instruction in &action.instructions {
+            if let Token::Call(called) = instruction {
+                if let Some(cycle) = find_cycle(called, actions, calling, checked) {
+                    return Some(cycle);
                 }
-                let instruction: Token = match raw_instruction[0] {
-                    "move" => Token::MouseMove {
-                        direction: match raw_instruction[1] {
-                            "up" => Direction::Up,
-                            "down" => Direction::Down,
-                            "left" => Direction::Left,
-                            "right" => Direction::Right,
-                            _ => {
-                                panic!(
-                                    "Invalid mouse move direction in 'move' instruction, line {}",
-                                    line_num
-                                )
-                            }
+            }
+        }
+        calling.pop();
+        checked.insert(name.to_string());
+        None
+    }
+
+    // Adds up the waits in some instructions, including the actions they call.
+    // Stops at Duration::MAX instead of overflowing, however long the waits are.
+    fn wait_of(instructions: &[Token], called_wait: &mut dyn FnMut(&str) -> Duration) -> Duration {
+        let mut total = Duration::ZERO;
+        for instruction in instructions {
+            match instruction {
+                Token::Wait(time) => total = total.saturating_add(Duration::from_millis(*time)),
+                Token::Call(called) => total = total.saturating_add(called_wait(called)),
+                _ => {}
+            }
+        }
+        total
+    }
+
+    // Turns one line of an action into tokens.
+    // `hold` expands into a press, a wait and a release, so this can push more than one.
+    fn parse_instruction(
+        words: &[(usize, &str)],
+        line_num: usize,
+        // Every name an action can be called by, and the action it belongs to
+        action_names: &HashMap<String, String>,
+        instructions: &mut Vec<Token>,
+    ) -> Result<(), ParseError> {
+        let (column, instruction) = words[0];
+        match instruction {
+            "move" => {
+                let (column, direction) =
+                    argument(words, 1, line_num, "direction", "move left 500")?;
+                let direction = direction_from_name(direction).ok_or_else(|| {
+                    ParseError::new(
+                        line_num,
+                        column,
+                        direction,
+                        "Invalid mouse move direction in 'move' instruction".to_string(),
+                    )
+                    .suggest(did_you_mean(direction, DIRECTION_NAMES))
+                })?;
+                let (column, distance) = argument(words, 2, line_num, "distance", "move left 500")?;
+                let distance = distance.parse::<i32>().map_err(|_| {
+                    ParseError::new(
+                        line_num,
+                        column,
+                        distance,
+                        "Invalid distance in 'move' instruction".to_string(),
+                    )
+                    .suggest("The distance is a whole number of pixels".to_string())
+                })?;
+                instructions.push(Token::MouseMove {
+                    direction,
+                    distance,
+                });
+            }
+            "press" | "hold" | "release" => {
+                let release = instruction == "release";
+                let (column, name) = argument(words, 1, line_num, "key", "press space")?;
+                let time_index = if name == "mouse" { 3 } else { 2 };
+                // `keep` is only allowed after the key of a `press`
+                let keep = match words.get(time_index) {
+                    Some((_, "keep")) if instruction == "press" => true,
+                    Some((column, "keep")) => {
+                        return Err(ParseError::new(
+                            line_num,
+                            *column,
+                            "keep",
+                            format!("'keep' can't be used in a '{}' instruction", instruction),
+                        )
+                        .suggest(format!(
+                            "Use 'press {} keep' to hold it down after the action ends",
+                            words[1..time_index]
+                                .iter()
+                                .map(|(_, word)| *word)
+                                .collect::<Vec<&str>>()
+                                .join(" ")
+                        )))
+                    }
+                    _ => false,
+                };
+                let token = if name == "mouse" {
+                    let (column, name) =
+                        argument(words, 2, line_num, "mouse button", "press mouse left")?;
+                    let button = mouse_button_from_name(name).ok_or_else(|| {
+                        ParseError::new(
+                            line_num,
+                            column,
+                            name,
+                            format!("Invalid mouse button in '{}' instruction", instruction),
+                        )
+                        .suggest(did_you_mean(name, MOUSE_BUTTON_NAMES))
+                    })?;
+                    Token::Click {
+                        button,
+                        release,
+                        keep,
+                    }
+                } else {
+                    let button = key_from_name(name).ok_or_else(|| {
+                        let error = ParseError::new(
+                            line_num,
+                            column,
+                            name,
+                            format!("Invalid key name in '{}' instruction", instruction),
+                        );
+                        if name == "middle" {
+                            error.suggest(format!(
+                                "Perhaps you meant to press a mouse button? ('{} mouse {}')",
+                                instruction, name
+                            ))
+                        } else {
+                            error.suggest(did_you_mean(name, KEY_NAMES))
+                        }
+                    })?;
+                    Token::Key {
+                        button,
+                        release,
+                        keep,
+                    }
+                };
+                if instruction == "hold" {
+                    let (column, time) =
+                        argument(words, time_index, line_num, "time", "hold space 500")?;
+                    let time = time.parse::<u64>().map_err(|_| {
+                        ParseError::new(
+                            line_num,
+                            column,
+                            time,
+                            "Invalid time in 'hold' instruction".to_string(),
+                        )
+                        .suggest("The time is a whole number of milliseconds".to_string())
+                    })?;
+                    let released = match token {
+                        Token::Key { button, .. } => Token::Key {
+                            button,
+                            release: true,
+                            keep: false,
                         },
-                        distance: raw_instruction[2].parse::<i32>().expect(
-                            format!("Invalid distance 'move' instruction, line {}", line_num)
-                                .as_str(),
-                        ),
+                        Token::Click { button, .. } => Token::Click {
+                            button,
+                            release: true,
+                            keep: false,
+                        },
+                        _ => unreachable!(),
+                    };
+                    instructions.push(token);
+                    instructions.push(Token::Wait(time));
+                    instructions.push(released);
+                } else {
+                    instructions.push(token);
+                }
+            }
+            "wait" => {
+                let (column, time) = argument(words, 1, line_num, "time", "wait 1000")?;
+                let time = time.parse::<u64>().map_err(|_| {
+                    ParseError::new(
+                        line_num,
+                        column,
+                        time,
+                        "Invalid time in 'wait' instruction".to_string(),
+                    )
+                    .suggest("The time is a whole number of milliseconds".to_string())
+                })?;
+                instructions.push(Token::Wait(time));
+            }
+            "type" => {
+                let text: Vec<&str> = words[1..].iter().map(|(_, word)| *word).collect();
+                instructions.push(Token::Type(text.join(" ")));
+            }
+            "screenshot" => instructions.push(Token::Screenshot),
+            _ => {
+                let trimmed_line: Vec<&str> = words.iter().map(|(_, word)| *word).collect();
+                let trimmed_line = trimmed_line.join(" ");
+                if let Some(name) = action_names.get(&trimmed_line) {
+                    instructions.push(Token::Call(name.clone()));
+                } else {
+                    let mut candidates: Vec<&str> = INSTRUCTION_NAMES.to_vec();
+                    candidates.extend(action_names.keys().map(|name| name.as_str()));
+                    return Err(ParseError::new(
+                        line_num,
+                        column,
+                        &trimmed_line,
+                        "Invalid instruction or unknown action".to_string(),
+                    )
+                    .suggest(match closest_name(&trimmed_line, &candidates) {
+                        Some(name) => format!("Did you mean '{}'?", name),
+                        None => "Actions must be defined somewhere in actions.txt before they can be called".to_string(),
+                    }));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Loads actions from a file, e.g. actions.txt next to the executable.
+    pub fn parse_action_file<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<HashMap<String, Action>, Vec<ParseError>> {
+        let path = path.as_ref();
+        match File::open(path) {
+            Ok(file) => parse_actions_from_reader(BufReader::new(file)),
+            Err(why) => Err(vec![ParseError::new(
+                0,
+                0,
+                &path.display().to_string(),
+                format!("Could not open the actions file: {}", why),
+            )]),
+        }
+    }
+
+    pub fn parse_actions_from_reader<R: BufRead>(
+        reader: R,
+    ) -> Result<HashMap<String, Action>, Vec<ParseError>> {
+        let mut lines: Vec<String> = vec![];
+        for (line_index, line) in reader.lines().enumerate() {
+            match line {
+                Ok(line) => lines.push(line),
+                Err(why) => {
+                    return Err(vec![ParseError::new(
+                        line_index + 1,
+                        0,
+                        "",
+                        format!("Could not read line: {}", why),
+                    )])
+                }
+            }
+        }
+        parse_lines(lines.iter().map(|line| line.as_str()))
+    }
+
+    pub fn parse_actions(source: &str) -> Result<HashMap<String, Action>, Vec<ParseError>> {
+        parse_lines(source.lines())
+    }
+
+    fn parse_lines<'a, I>(lines: I) -> Result<HashMap<String, Action>, Vec<ParseError>>
+    where
+        I: Iterator<Item = &'a str> + Clone,
+    {
+        let mut actions: HashMap<String, Action> = HashMap::new();
+        let mut errors: Vec<ParseError> = vec![];
+        // Names and aliases, and the action each one belongs to
+        let mut action_names: HashMap<String, String> = HashMap::new();
+        // Costs set in headers, the rest are worked out at the end
+        let mut costs: HashMap<String, Duration> = HashMap::new();
+        // Which action each emoji belongs to, since a reaction can only send one
+        let mut emojis: HashMap<String, String> = HashMap::new();
+        // Which action each name and alias belongs to, in the order they were found
+        let mut owners: HashMap<String, String> = HashMap::new();
+        // Where each finished action's name is
+        let mut headers: HashMap<String, (usize, usize)> = HashMap::new();
+
+        // Check every line for actions before compiling actions
+        for (line_index, the_line) in lines.clone().enumerate() {
+            let words = split_words(the_line);
+            let raw_instruction: Vec<&str> = words.iter().map(|(_, word)| *word).collect();
+            let trimmed_line = raw_instruction.join(" ");
+
+            if trimmed_line.ends_with(':') {
+                // Bad headers are reported in the second pass
+                if let Ok((
+                    Action {
+                        name: Some(action_name),
+                        aliases,
+                        pattern: None,
+                        ..
                     },
-                    "press" | "hold" | "release" => {
-                        let mut mouse: bool = false;
-
-                        let token = match raw_instruction[1] {
-                            "mouse" => {
-                                mouse = true;
-                                Token::Click {
-                                    button: match raw_instruction[2] {
-                                        "left" => enigo::MouseButton::Left,
-                                        "middle" => enigo::MouseButton::Middle,
-                                        "right" => enigo::MouseButton::Right,
-                                        _ => {
-                                            panic!(
-                                                "Invalid mouse button in '{}' instruction, line {}",
-                                                raw_instruction[0],
-                                                line_num
-                                            )
-                                        }
-                                    },
-                                    release: raw_instruction[0] == "release",
-                                }
-                            },
-                            _ => Token::Key {
-                                // Kill
-                                button: match raw_instruction[1] {
-                                    "alt" => enigo::Key::Alt,
-                                    "backspace" | "back" => enigo::Key::Backspace,
-                                    "caps_lock" => enigo::Key::CapsLock,
-                                    "control" | "ctrl" => enigo::Key::Control,
-                                    "del" | "delete" => enigo::Key::Delete,
-                                    "down" => enigo::Key::DownArrow,
-                                    "end" => enigo::Key::End,
-                                    "esc" | "escape" => enigo::Key::Escape,
-                                    "f1" => enigo::Key::F1,
-                                    "f10" => enigo::Key::F10,
-                                    "f11" => enigo::Key::F11,
-                                    "f12" => enigo::Key::F12,
-                                    "f2" => enigo::Key::F2,
-                                    "f3" => enigo::Key::F3,
-                                    "f4" => enigo::Key::F4,
-                                    "f5" => enigo::Key::F5,
-                                    "f6" => enigo::Key::F6,
-                                    "f7" => enigo::Key::F7,
-                                    "f8" => enigo::Key::F8,
-                                    "f9" => enigo::Key::F9,
-                                    "home" => enigo::Key::Home,
-                                    "left" => enigo::Key::LeftArrow,
-                                    "win" | "windows" | "meta" | "command" | "super" => enigo::Key::Meta,
-                                    "option" => enigo::Key::Option,
-                                    "pgdown" | "pg_down" | "page_down" => enigo::Key::PageDown,
-                                    "pgup" | "pg_up" | "page_up" => enigo::Key::PageUp,
-                                    "return" | "enter" => enigo::Key::Return,
-                                    "right" => enigo::Key::RightArrow,
-                                    "shift" => enigo::Key::Shift,
-                                    "space" => enigo::Key::Space,
-                                    "tab" => enigo::Key::Tab,
-                                    "up" => enigo::Key::UpArrow,
-                                    // If there's a better way to check if a character is a key I'd love to hear it
-                                    "a" | "b" | "c" | "d" | "e" | "f" | "g" | "h" | "i" | "j" | "k"
-                                        | "l" | "m" | "n" | "o" | "p" | "q" | "r" | "s" | "t" | "u" | "v"
-                                        | "w" | "x" | "y" | "z" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" | "`" | "-" | "=" | "[" | "]" | "\\" | ";" | "'" | "," | "." | "/"  => enigo::Key::Layout(
-                                        raw_instruction[1].chars().next().expect(
+                    _,
+                )) = parse_header(&words, line_index + 1)
+                {
+                    // Clashes are reported in the second pass
+                    for alias in aliases {
+                        action_names
+                            .entry(alias)
+                            .or_insert_with(|| action_name.clone());
+                    }
+                    action_names.insert(action_name.clone(), action_name);
+                }
+                if INSTRUCTION_NAMES.contains(&raw_instruction[0]) {
+                    println!(
+                        "WARNING: action with same name as builtin instruction at line {}",
+                        line_index + 1
+                    );
+                }
+            }
+        }
+
+        // The action being built, along with the line its name is on
+        let mut action: Option<(Action, usize, usize)> = None;
+
+        let mut line_num: usize = 0;
+        // An example of my incredibly sophisticated naming system
+        for the_line in lines {
+            line_num += 1;
+            let words = split_words(the_line);
+
+            if words.is_empty() {
+                continue;
+            }
+
+            let raw_instruction: Vec<&str> = words.iter().map(|(_, word)| *word).collect();
+            let trimmed_line = raw_instruction.join(" ");
+
+            if trimmed_line.ends_with(':') {
+                if let Some((unfinished, name_line, name_column)) = action.take() {
+                    let name = unfinished.name.unwrap_or_default();
+                    errors.push(
+                        ParseError::new(
+                            name_line,
+                            name_column,
+                            &name,
+                            format!("Action '{}' is missing an 'end'", name),
+                        )
+                        .suggest(format!(
+                            "Add 'end' on its own line before line {}",
+                            line_num
+                        )),
+                    );
+                }
+                match parse_header(&words, line_num) {
+                    Ok((header, cost)) => {
+                        if let (Some(name), Some(cost)) = (&header.name, cost) {
+                            costs.insert(name.clone(), cost);
+                        }
+                        if let Some(name) = &header.name {
+                            for alias in std::iter::once(name).chain(&header.aliases) {
+                                match owners.get(alias) {
+                                    Some(other) if other != name => errors.push(
+                                        ParseError::new(
+                                            line_num,
+                                            words[0].0,
+                                            alias,
                                             format!(
-                                                "Invalid character in '{}' at {} (This shouldn't happen)",
-                                                raw_instruction[0],
-                                                line_num
-                                            )
-                                            .as_str(),
+                                                "'{}' is already a name of action '{}'",
+                                                alias, other
+                                            ),
+                                        )
+                                        .suggest(
+                                            "Each name or alias can only belong to one action"
+                                                .to_string(),
                                         ),
                                     ),
                                     _ => {
-                                        panic!(
-                                            "Invalid key name '{}' in '{}' instruction, line {}\n{}",
-                                            raw_instruction[1],
-                                            raw_instruction[0],
-                                            line_num,
-                                            if raw_instruction[1] == "right"
-                                                || raw_instruction[1] == "left"
-                                                || raw_instruction[1] == "middle"
-                                            {
-                                                "Perhaps you meant to press a mouse button?"
-                                            } else {
-                                                ""
-                                            }
-                                        )
+                                        owners.insert(alias.clone(), name.clone());
                                     }
-                                },
-                                release: raw_instruction[0] == "release",
-                            },
-                        };
-                        if raw_instruction[0] == "hold" {
-                            action.instructions.push(token.clone());
-                            action.instructions.push(Token::Wait(
-                                (if mouse {
-                                    raw_instruction[3]
-                                } else {
-                                    raw_instruction[2]
-                                })
-                                .parse::<u64>()
-                                .expect(
-                                    format!(
-                                        "Invalid time in 'hold' instruction, line {}",
-                                        line_num
+                                }
+                            }
+                        }
+                        if let (Some(name), Some(emoji)) = (&header.name, &header.emoji) {
+                            // '⬆' and '⬆️' are the same emoji on Discord
+                            let emoji = emoji.replace('\u{fe0f}', "");
+                            match emojis.get(&emoji) {
+                                Some(other) if other != name => errors.push(
+                                    ParseError::new(
+                                        line_num,
+                                        words[0].0,
+                                        name,
+                                        format!(
+                                            "Action '{}' has the same emoji as '{}'",
+                                            name, other
+                                        ),
                                     )
-                                    .as_str(),
+                                    .suggest("Give each action its own emoji".to_string()),
                                 ),
-                            ));
-                            match token {
-                                Token::Key { button, release } => Token::Key {
-                                    button: button,
-                                    release: !release,
-                                },
-                                Token::Click { button, release } => Token::Click {
-                                    button: button,
-                                    release: !release,
-                                },
                                 _ => {
-                                    panic!(
-                                        "Invalid token in '{}' instruction. This shouldn't happen.",
-                                        raw_instruction[0]
-                                    );
+                                    emojis.insert(emoji, name.clone());
                                 }
                             }
-                        } else {
-                            token
-                        }
-                    },
-                    "wait" => Token::Wait(raw_instruction[1].parse::<u64>().expect(
-                        format!("Invalid time in 'wait' instruction, line {}", line_num).as_str(),
-                    )),
-                    "type" => {
-                        raw_instruction.remove(0);
-                        Token::Type(raw_instruction.join(" "))
-                    },
-                    "screenshot" => Token::Screenshot,
-                    "end" => Token::End,
-                    _ => {
-                        if actions.contains_key(&trimmed_line) {
-                            Token::Call(trimmed_line)
-                        } else {
-                            panic!("Invalid instruction, line {}", line_num)
                         }
+                        action = Some((header, line_num, words[0].0));
                     }
-                };
-                if instruction == Token::End {
-                    // I hate this and everything about this.
-                    action.instructions.push(instruction);
-                    actions.insert(
-                        action
-                            .name
-                            .as_ref()
-                            .expect("No name for action")
-                            .to_string(),
-                        action,
+                    Err(error) => {
+                        errors.push(error);
+                        // Keep going with a nameless action, so its instructions
+                        // are still checked without being reported as stray
+                        action = Some((Action::default(), line_num, words[0].0));
+                    }
+                }
+                continue;
+            }
+
+            let (current, _, _) = match action.as_mut() {
+                Some(current) => current,
+                None => {
+                    errors.push(
+                        ParseError::new(
+                            line_num,
+                            words[0].0,
+                            &trimmed_line,
+                            "Instruction outside of an action".to_string(),
+                        )
+                        .suggest(
+                            "Start an action with its name followed by ':', like 'Jump:'"
+                                .to_string(),
+                        ),
                     );
-                    action = Action {
-                        name: None,
-                        instructions: vec![],
-                    };
+                    continue;
+                }
+            };
+
+            if raw_instruction[0] == "end" {
+                // I hate this and everything about this.
+                let (mut finished, name_line, name_column) = action.take().unwrap();
+                finished.instructions.push(Token::End);
+                if let Some(name) = finished.name.clone() {
+                    headers.insert(name.clone(), (name_line, name_column));
+                    actions.insert(name, finished);
+                }
+                continue;
+            }
+
+            if let Some(pattern) = current.pattern.as_mut() {
+                if let Err(error) = check_pattern_line(&words, line_num, pattern, &action_names) {
+                    errors.push(error);
+                }
+                pattern.lines.push((line_num, trimmed_line));
+                continue;
+            }
+
+            if let Err(error) =
+                parse_instruction(&words, line_num, &action_names, &mut current.instructions)
+            {
+                errors.push(error);
+            }
+        }
+
+        if let Some((unfinished, name_line, name_column)) = action {
+            let name = unfinished.name.unwrap_or_default();
+            errors.push(
+                ParseError::new(
+                    name_line,
+                    name_column,
+                    &name,
+                    format!("Action '{}' is missing an 'end'", name),
+                )
+                .suggest("Add 'end' on its own line at the end of the file".to_string()),
+            );
+        }
+
+        // An action that ends up calling itself would never finish.
+        // Each loop is reported once, at the first action in it.
+        let mut names: Vec<String> = actions.keys().cloned().collect();
+        names.sort_by_key(|name| headers.get(name).copied());
+        let mut checked: HashSet<String> = HashSet::new();
+        let mut reported: HashSet<String> = HashSet::new();
+        for name in &names {
+            if reported.contains(name) {
+                continue;
+            }
+            if let Some(cycle) = find_cycle(name, &actions, &mut vec![], &mut checked) {
+                // Reached from an action that calls into one already reported
+                if reported.contains(&cycle[0]) {
+                    continue;
+                }
+                let (line, column) = headers[&cycle[0]];
+                let message = if cycle.len() == 2 {
+                    format!("Action '{}' calls itself", cycle[0])
                 } else {
-                    action.instructions.push(instruction);
+                    format!(
+                        "Action '{}' calls itself through {}",
+                        cycle[0],
+                        cycle.join(" -> ")
+                    )
+                };
+                errors.push(ParseError::new(line, column, &cycle[0], message).suggest(
+                    "Actions can't call themselves, even through other actions".to_string(),
+                ));
+                reported.extend(cycle);
+            }
+        }
+
+        let mut durations = HashMap::new();
+        for name in &names {
+            total_wait(name, &actions, &mut vec![], &mut durations);
+        }
+        for name in names {
+            let duration = durations[&name];
+            if let Some(action) = actions.get_mut(&name) {
+                action.duration = duration;
+                action.cost = costs.get(&name).copied().unwrap_or(duration);
+                if let Some(pattern) = action.pattern.as_mut() {
+                    pattern.cost = costs.get(&name).copied();
                 }
             }
         }
-        return actions;
+
+        if errors.is_empty() {
+            Ok(actions)
+        } else {
+            errors.sort_by_key(|error| (error.line, error.column));
+            Err(errors)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn parse_errors(source: &str) -> Vec<ParseError> {
+            match parse_actions(source) {
+                Ok(_) => panic!("expected errors in {:?}", source),
+                Err(errors) => errors,
+            }
+        }
+
+        #[test]
+        fn parses_instructions() {
+            let actions = parse_actions("Jump:\nhold space 750 // a comment\nend\n").unwrap();
+            let jump = &actions["Jump"];
+            assert_eq!(jump.duration, Duration::from_millis(750));
+            assert_eq!(jump.cost, jump.duration);
+            assert!(
+                jump.instructions
+                    == [
+                        Token::Key {
+                            button: enigo::Key::Space,
+                            release: false,
+                            keep: false,
+                        },
+                        Token::Wait(750),
+                        Token::Key {
+                            button: enigo::Key::Space,
+                            release: true,
+                            keep: false,
+                        },
+                        Token::End,
+                    ]
+            );
+        }
+
+        #[test]
+        fn bad_arguments_point_at_the_word() {
+            let errors = parse_errors("Look:\nmove sideways 10\nend\n");
+            assert_eq!(errors.len(), 1);
+            assert_eq!((errors[0].line, errors[0].column), (2, 6));
+            assert_eq!(errors[0].token, "sideways");
+
+            let errors = parse_errors("Jump:\n  hold spce 100\nend\n");
+            assert_eq!((errors[0].line, errors[0].column), (2, 8));
+            assert_eq!(
+                errors[0].suggestion.as_deref(),
+                Some("Did you mean 'space'?")
+            );
+        }
+
+        #[test]
+        fn missing_arguments_point_after_the_line() {
+            let errors = parse_errors("Wait:\nwait\nend\n");
+            assert_eq!((errors[0].line, errors[0].column), (2, 5));
+            assert_eq!(errors[0].message, "Missing time in 'wait' instruction");
+        }
+
+        #[test]
+        fn missing_end_is_reported_at_the_name() {
+            let errors = parse_errors("Jump:\npress space\nLeft:\npress a\n");
+            assert_eq!(errors.len(), 2);
+            assert_eq!((errors[0].line, errors[0].column), (1, 1));
+            assert_eq!(errors[0].message, "Action 'Jump' is missing an 'end'");
+            assert_eq!(errors[1].line, 3);
+        }
+
+        #[test]
+        fn every_error_is_reported_in_order() {
+            let errors = parse_errors("press a\nJump:\nhold space soon\nwait x\nend\n");
+            let lines: Vec<usize> = errors.iter().map(|error| error.line).collect();
+            assert_eq!(lines, [1, 3, 4]);
+            assert_eq!(errors[0].message, "Instruction outside of an action");
+        }
+
+        #[test]
+        fn errors_display_their_position() {
+            let error = &parse_errors("Look:\nmove sideways 10\nend\n")[0];
+            assert_eq!(
+                error.to_string(),
+                "line 2, column 6: Invalid mouse move direction in 'move' instruction (at 'sideways')\n  Expected one of: up, down, left, right"
+            );
+        }
+
+        #[test]
+        fn reads_from_any_reader() {
+            let source = "Jump:\nhold space 100\nend\n";
+            let from_reader = parse_actions_from_reader(source.as_bytes()).unwrap();
+            assert_eq!(from_reader.len(), 1);
+            assert_eq!(from_reader["Jump"].duration, Duration::from_millis(100));
+        }
+
+        #[test]
+        fn missing_files_are_an_error_not_a_new_file() {
+            let path = std::env::temp_dir().join("discord_plays_missing_actions.txt");
+            let errors = parse_action_file(&path).err().unwrap();
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].line, 0);
+            assert!(!path.exists());
+        }
+
+        #[test]
+        fn aliases_resolve_to_the_action() {
+            let actions =
+                parse_actions("MoveLeft | left|l:\nhold a 100\nend\nCombo:\nl\nleft\nend\n")
+                    .unwrap();
+            assert_eq!(actions.len(), 2);
+            assert_eq!(actions["MoveLeft"].aliases, ["left", "l"]);
+            assert!(
+                actions["Combo"].instructions
+                    == [
+                        Token::Call("MoveLeft".to_string()),
+                        Token::Call("MoveLeft".to_string()),
+                        Token::End,
+                    ]
+            );
+            assert_eq!(actions["Combo"].duration, Duration::from_millis(200));
+        }
+
+        #[test]
+        fn aliases_cant_be_shared() {
+            let errors = parse_errors("Look | l:\npress a\nend\nLeft | l:\npress b\nend\n");
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].line, 4);
+            assert_eq!(errors[0].message, "'l' is already a name of action 'Look'");
+
+            let errors = parse_errors("Jump | Walk:\npress a\nend\nWalk:\npress b\nend\n");
+            assert_eq!(
+                errors[0].message,
+                "'Walk' is already a name of action 'Jump'"
+            );
+        }
+
+        #[test]
+        fn empty_aliases_are_errors() {
+            let errors = parse_errors("Jump | :\npress a\nend\n");
+            assert_eq!((errors[0].line, errors[0].column), (1, 7));
+            assert_eq!(errors[0].message, "Empty alias");
+        }
+
+        const WALK: &str =
+            "/walk (left|right) (\\d+)/ [cooldown=2s]:\nhold $1 $2\nend\n/tap (\\w+)/ [cost=3s]:\npress $1\nend\n";
+
+        #[test]
+        fn patterns_fill_in_captures() {
+            let actions = parse_actions(WALK).unwrap();
+            let walk = match_pattern("walk left 500", &actions).unwrap().unwrap();
+            assert_eq!(walk.name.as_deref(), Some("/walk (left|right) (\\d+)/"));
+            assert_eq!(walk.cooldown, Some(Duration::from_secs(2)));
+            assert_eq!(walk.duration, Duration::from_millis(500));
+            assert_eq!(walk.cost, walk.duration);
+            assert!(walk.pattern.is_none());
+            assert!(
+                walk.instructions[..2]
+                    == [
+                        Token::Key {
+                            button: enigo::Key::LeftArrow,
+                            release: false,
+                            keep: false,
+                        },
+                        Token::Wait(500),
+                    ]
+            );
+            assert_eq!(
+                resolve("tap q", &actions).unwrap().cost,
+                Duration::from_secs(3)
+            );
+        }
+
+        #[test]
+        fn patterns_match_the_whole_text() {
+            let actions = parse_actions(WALK).unwrap();
+            assert!(match_pattern("walk up 500", &actions).is_none());
+            assert!(match_pattern("please walk left 500", &actions).is_none());
+            // Patterns can't be typed by their name
+            assert!(resolve("/walk (left|right) (\\d+)/", &actions).is_none());
+        }
+
+        #[test]
+        fn captures_are_checked_like_actions_txt() {
+            let actions = parse_actions(WALK).unwrap();
+            let error = match_pattern("tap sideways", &actions)
+                .unwrap()
+                .err()
+                .unwrap();
+            assert_eq!(error.token, "sideways");
+            assert_eq!(error.message, "Invalid key name in 'press' instruction");
+            assert!(resolve("tap sideways", &actions).is_none());
+        }
+
+        #[test]
+        fn pattern_lines_are_checked_when_loaded() {
+            let errors = parse_errors("/walk (\\w+)/:\nhold $2 100\nend\n");
+            assert_eq!((errors[0].line, errors[0].column), (2, 6));
+            assert_eq!(errors[0].message, "'$2' isn't a group in the pattern");
+
+            let errors = parse_errors("/walk (\\w+) (\\d+)/:\nhodl $1 $2\nend\n");
+            assert_eq!(errors[0].token, "hodl");
+            assert_eq!(
+                errors[0].suggestion.as_deref(),
+                Some("Did you mean 'hold'?")
+            );
+
+            let errors = parse_errors("/walk (left/:\nend\n");
+            assert_eq!(errors[0].message, "Invalid pattern: unclosed group");
+
+            let errors = parse_errors("/walk/ [emoji=⬆️]:\nend\n");
+            assert_eq!(errors[0].message, "Pattern actions can't have an emoji");
+        }
+
+        #[test]
+        fn settings_are_read_from_the_header() {
+            let actions = parse_actions(
+                "Build [cooldown=2s, cost=5s,level=manager, roles=Trusted|123]:\npress b\nend\n",
+            )
+            .unwrap();
+            let build = &actions["Build"];
+            assert_eq!(build.cooldown, Some(Duration::from_secs(2)));
+            assert_eq!(build.cost, Duration::from_secs(5));
+            assert_eq!(build.level, Level::Manager);
+            assert_eq!(
+                build.roles,
+                [RoleRef::Name("Trusted".to_string()), RoleRef::Id(123)]
+            );
+        }
+
+        #[test]
+        fn quoted_roles_can_have_spaces() {
+            let actions = parse_actions(
+                "Quit [roles=\"Discord Plays Manager\" | VIP, level=\"mod\"]:\npress esc\nend\n",
+            )
+            .unwrap();
+            assert_eq!(
+                actions["Quit"].roles,
+                [
+                    RoleRef::Name("Discord Plays Manager".to_string()),
+                    RoleRef::Name("VIP".to_string()),
+                ]
+            );
+            assert_eq!(actions["Quit"].level, Level::Mod);
+        }
+
+        #[test]
+        fn spaces_outside_quotes_are_errors() {
+            let errors = parse_errors("Quit [roles=Discord Plays Manager]:\nend\n");
+            assert_eq!((errors[0].line, errors[0].column), (1, 13));
+            assert_eq!(errors[0].message, "Role names with spaces need quotes");
+            assert_eq!(
+                errors[0].suggestion.as_deref(),
+                Some("Write it as 'roles=\"Discord Plays Manager\"'")
+            );
+
+            let errors = parse_errors("Quit [level=mod, roles=\"Discord Plays]:\nend\n");
+            assert_eq!((errors[0].line, errors[0].column), (1, 24));
+            assert_eq!(
+                errors[0].message,
+                "Missing closing '\"' in the action's settings"
+            );
+
+            let errors = parse_errors("Save [cooldown=5 s]:\nend\n");
+            assert_eq!((errors[0].line, errors[0].column), (1, 16));
+        }
+
+        // A0 waits, and every action after it calls the one before it twice
+        fn doubling_actions(levels: usize, wait: &str) -> String {
+            let mut source = format!("A0:\nwait {}\nwait {}\nend\n", wait, wait);
+            for level in 1..levels {
+                source += &format!("A{}:\nA{}\nA{}\nend\n", level, level - 1, level - 1);
+            }
+            source
+        }
+
+        #[test]
+        fn huge_waits_add_up_without_overflowing() {
+            let actions = parse_actions(&doubling_actions(12, "18446744073709551615")).unwrap();
+            assert_eq!(actions["A11"].duration, Duration::MAX);
+            assert_eq!(actions["A11"].cost, Duration::MAX);
+        }
+
+        #[test]
+        fn deep_calls_are_added_up_once() {
+            let actions = parse_actions(&doubling_actions(40, "1")).unwrap();
+            assert_eq!(actions["A39"].duration, Duration::from_millis(1 << 40));
+        }
     }
 }
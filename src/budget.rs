@@ -0,0 +1,153 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+// Each player gets a budget of input time that slowly fills back up,
+// so long macros use up more of it than quick taps.
+pub struct Budgets {
+    // The most anyone can save up
+    pub capacity: Duration,
+    // Seconds of budget given back every second
+    pub refill_rate: f64,
+    // How much each user had, and when that was
+    users: HashMap<u64, (Duration, Instant)>,
+}
+
+impl Budgets {
+    pub fn new(capacity: Duration, refill_rate: f64) -> Budgets {
+        Budgets {
+            capacity,
+            refill_rate,
+            users: HashMap::new(),
+        }
+    }
+
+    // Everyone starts with a full budget
+    pub fn remaining(&self, user: u64, now: Instant) -> Duration {
+        match self.users.get(&user) {
+            Some((left, since)) => refill(*left, *since, now, self.refill_rate).min(self.capacity),
+            None => self.capacity,
+        }
+    }
+
+    // Actions that cost more than the whole budget can still be used with a full budget
+    fn price(&self, cost: Duration) -> Duration {
+        cost.min(self.capacity)
+    }
+
+    pub fn can_afford(&self, user: u64, cost: Duration, now: Instant) -> bool {
+        self.remaining(user, now) >= self.price(cost)
+    }
+
+    // Takes the cost out of the user's budget.
+    // If they can't afford it, returns how long until they can.
+    pub fn try_spend(&mut self, user: u64, cost: Duration, now: Instant) -> Result<(), Duration> {
+        let remaining = self.remaining(user, now);
+        let price = self.price(cost);
+        if remaining < price {
+            if self.refill_rate <= 0.0 {
+                return Err(Duration::MAX);
+            }
+            return Err((price - remaining).div_f64(self.refill_rate));
+        }
+        self.users.insert(user, (remaining - price, now));
+        // Full budgets are the same as not being here at all
+        let capacity = self.capacity;
+        let refill_rate = self.refill_rate;
+        self.users
+            .retain(|_, (left, since)| refill(*left, *since, now, refill_rate) < capacity);
+        Ok(())
+    }
+
+    // Takes the cost out whether or not they can afford it, down to an empty budget.
+    // Used when a vote they were part of wins, since they could afford it when they voted.
+    pub fn spend(&mut self, user: u64, cost: Duration, now: Instant) {
+        let remaining = self.remaining(user, now);
+        let price = self.price(cost);
+        self.users
+            .insert(user, (remaining.saturating_sub(price), now));
+    }
+}
+
+// What was left at `since`, plus what came back by `now`.
+// Refills too big to fit in a Duration are as good as full.
+fn refill(left: Duration, since: Instant, now: Instant, refill_rate: f64) -> Duration {
+    let seconds = now.saturating_duration_since(since).as_secs_f64() * refill_rate;
+    let refilled = Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX);
+    left.saturating_add(refilled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn budgets_start_full_and_refill() {
+        let start = Instant::now();
+        let mut budgets = Budgets::new(SECOND * 30, 0.5);
+        assert_eq!(budgets.remaining(1, start), SECOND * 30);
+        assert_eq!(budgets.try_spend(1, SECOND * 10, start), Ok(()));
+        assert_eq!(budgets.remaining(1, start), SECOND * 20);
+        assert_eq!(budgets.remaining(1, start + SECOND * 4), SECOND * 22);
+        // Never past the capacity
+        assert_eq!(budgets.remaining(1, start + SECOND * 60), SECOND * 30);
+    }
+
+    #[test]
+    fn spending_too_much_says_how_long_to_wait() {
+        let start = Instant::now();
+        let mut budgets = Budgets::new(SECOND * 10, 0.5);
+        budgets.try_spend(1, SECOND * 8, start).unwrap();
+        assert!(!budgets.can_afford(1, SECOND * 3, start));
+        assert_eq!(budgets.try_spend(1, SECOND * 3, start), Err(SECOND * 2));
+        // Nothing was taken out for the failed try
+        assert_eq!(budgets.remaining(1, start), SECOND * 2);
+        assert_eq!(budgets.try_spend(1, SECOND * 3, start + SECOND * 2), Ok(()));
+    }
+
+    #[test]
+    fn actions_over_the_capacity_need_a_full_budget() {
+        let start = Instant::now();
+        let mut budgets = Budgets::new(SECOND * 10, 1.0);
+        assert!(budgets.can_afford(1, Duration::MAX, start));
+        assert_eq!(budgets.try_spend(1, Duration::MAX, start), Ok(()));
+        assert_eq!(budgets.remaining(1, start), Duration::ZERO);
+        assert_eq!(
+            budgets.try_spend(1, SECOND * 60, start + SECOND),
+            Err(SECOND * 9)
+        );
+    }
+
+    #[test]
+    fn budgets_that_never_refill_say_so() {
+        let start = Instant::now();
+        let mut budgets = Budgets::new(SECOND, 0.0);
+        budgets.try_spend(1, SECOND, start).unwrap();
+        assert_eq!(
+            budgets.try_spend(1, SECOND, start + SECOND * 100),
+            Err(Duration::MAX)
+        );
+    }
+
+    #[test]
+    fn spend_stops_at_empty() {
+        let start = Instant::now();
+        let mut budgets = Budgets::new(SECOND * 10, 1.0);
+        budgets.spend(1, SECOND * 4, start);
+        budgets.spend(1, SECOND * 8, start);
+        assert_eq!(budgets.remaining(1, start), Duration::ZERO);
+        assert_eq!(budgets.remaining(2, start), SECOND * 10);
+    }
+
+    #[test]
+    fn huge_refill_rates_just_fill_up() {
+        let start = Instant::now();
+        let mut budgets = Budgets::new(SECOND * 10, 1e300);
+        budgets.try_spend(1, SECOND * 10, start).unwrap();
+        assert_eq!(budgets.remaining(1, start + SECOND * 3600), SECOND * 10);
+        assert_eq!(budgets.try_spend(1, SECOND, start + SECOND), Ok(()));
+    }
+}
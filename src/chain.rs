@@ -0,0 +1,509 @@
+use std::{borrow::Cow, collections::HashMap, fmt, time::Duration};
+
+use serde::Deserialize;
+
+use crate::parsing::{self, levenshtein, Action, ParseError};
+
+// How much one message can ask for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChainLimits {
+    pub max_actions: usize,
+    // How long the actions can take to run, all together
+    pub max_duration: Duration,
+}
+
+impl Default for ChainLimits {
+    fn default() -> ChainLimits {
+        ChainLimits {
+            max_actions: 5,
+            max_duration: Duration::from_secs(15),
+        }
+    }
+}
+
+// How what players type is matched to action names
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Matching {
+    pub case_sensitive: bool,
+    // How many typos a message can have and still get a "did you mean". 0 turns that off.
+    pub suggest_distance: usize,
+}
+
+impl Default for Matching {
+    fn default() -> Matching {
+        Matching {
+            case_sensitive: false,
+            suggest_distance: 2,
+        }
+    }
+}
+
+// Every name an action can be typed as, with the action's own name.
+// Pattern actions are typed as whatever matches, so they have none.
+fn triggers(actions: &HashMap<String, Action>) -> impl Iterator<Item = (&str, &str)> {
+    actions
+        .iter()
+        .filter(|(_, action)| action.pattern.is_none())
+        .flat_map(|(name, action)| {
+            std::iter::once(name)
+                .chain(&action.aliases)
+                .map(move |trigger| (trigger.as_str(), name.as_str()))
+        })
+}
+
+// The action a typed name or alias means. The exact name always wins, then an exact
+// alias, then any that only differ in case (the first in alphabetical order, if there are several).
+pub fn find_action<'a>(
+    name: &str,
+    actions: &'a HashMap<String, Action>,
+    matching: &Matching,
+) -> Option<&'a str> {
+    if let Some((name, action)) = actions.get_key_value(name) {
+        if action.pattern.is_none() {
+            return Some(name);
+        }
+    }
+    if let Some((_, action)) = triggers(actions).find(|(trigger, _)| *trigger == name) {
+        return Some(action);
+    }
+    if matching.case_sensitive {
+        return None;
+    }
+    let lowercase = name.to_lowercase();
+    triggers(actions)
+        .filter(|(trigger, _)| trigger.to_lowercase() == lowercase)
+        .map(|(_, action)| action)
+        .min()
+}
+
+// The action a message was probably meant to be, for messages that aren't actions.
+// Only close misses count, so normal chat doesn't get suggestions.
+pub fn suggest_action<'a>(
+    message: &str,
+    actions: &'a HashMap<String, Action>,
+    matching: &Matching,
+) -> Option<&'a str> {
+    if matching.suggest_distance == 0 {
+        return None;
+    }
+    let fold = |text: &str| {
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if matching.case_sensitive {
+            text
+        } else {
+            text.to_lowercase()
+        }
+    };
+    let typed = fold(message);
+    triggers(actions)
+        .map(|(trigger, _)| (levenshtein(&typed, &fold(trigger)), trigger))
+        // Short names need fewer typos, or "ok" would be a typo of "up"
+        .filter(|(distance, name)| {
+            *distance > 0
+                && *distance <= matching.suggest_distance
+                && *distance * 2 <= name.chars().count()
+        })
+        .min()
+        .map(|(_, name)| name)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChainError {
+    // Something in it isn't an action, so it's probably just chat
+    NotActions,
+    // How many actions it asked for
+    TooManyActions(usize),
+    // How long it would have taken
+    TooLong(Duration),
+    // An action with its own cooldown came up more than once
+    RepeatsCooldown(String),
+    // It matched a pattern action, but what it filled in can't be used, like `walk sideways 500`
+    InvalidArgument(ParseError),
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::NotActions => write!(f, "That isn't a list of actions"),
+            ChainError::TooManyActions(count) => write!(f, "That is {} actions at once", count),
+            ChainError::TooLong(duration) => write!(
+                f,
+                "Those actions would take {:.1}s to run",
+                duration.as_secs_f64()
+            ),
+            ChainError::RepeatsCooldown(name) => {
+                write!(f, "'{}' has a cooldown, so it can only be used once", name)
+            }
+            ChainError::InvalidArgument(error) => {
+                write!(f, "'{}' doesn't work there: {}", error.token, error.message)
+            }
+        }
+    }
+}
+
+// Reads a message like `left left jump` or `left*3 jump` into the actions to run, in order.
+// Repeats can be written `*3` or `x3`. Action names can have spaces in them,
+// so the longest name that fits is tried first. Extra spaces don't matter.
+// Where no name fits, the longest text that matches a pattern action is used,
+// like `walk left 500`, and that text is what goes in the list.
+pub fn parse_chain(
+    message: &str,
+    actions: &HashMap<String, Action>,
+    limits: &ChainLimits,
+    matching: &Matching,
+) -> Result<Vec<String>, ChainError> {
+    let words: Vec<&str> = message.split_whitespace().collect();
+    let longest_name = triggers(actions)
+        .map(|(trigger, _)| trigger.split_whitespace().count())
+        .max()
+        .unwrap_or(0);
+
+    // What to run, how many times, and the action it means
+    let mut steps: Vec<(String, usize, Cow<Action>)> = vec![];
+    let mut start = 0;
+    while start < words.len() {
+        let longest = longest_name.min(words.len() - start);
+        let named = (1..=longest).rev().find_map(|len| {
+            let candidate = words[start..start + len].join(" ");
+            let (name, count) = match find_action(&candidate, actions, matching) {
+                Some(name) => (name, 1),
+                None => {
+                    let (name, count) = split_repeat(&candidate)?;
+                    (find_action(name, actions, matching)?, count)
+                }
+            };
+            Some((len, name.to_string(), count, Cow::Borrowed(&actions[name])))
+        });
+        // The first pattern that matched but couldn't be filled in, if nothing else fits
+        let mut invalid = None;
+        let step = named.or_else(|| {
+            (1..=words.len() - start).rev().find_map(|len| {
+                let candidate = words[start..start + len].join(" ");
+                let (text, count, found) = match parsing::match_pattern(&candidate, actions) {
+                    Some(found) => (candidate, 1, found),
+                    None => {
+                        let (text, count) = split_repeat(&candidate)?;
+                        (
+                            text.to_string(),
+                            count,
+                            parsing::match_pattern(text, actions)?,
+                        )
+                    }
+                };
+                match found {
+                    Ok(action) => Some((len, text, count, Cow::Owned(action))),
+                    Err(error) => {
+                        invalid.get_or_insert(error);
+                        None
+                    }
+                }
+            })
+        });
+        match step {
+            Some((len, text, count, action)) => {
+                steps.push((text, count, action));
+                start += len;
+            }
+            None => return Err(invalid.map_or(ChainError::NotActions, ChainError::InvalidArgument)),
+        }
+    }
+    if steps.is_empty() {
+        return Err(ChainError::NotActions);
+    }
+
+    // Checked before anything is repeated, so `left*99999999` stays cheap.
+    // A single action from actions.txt is never too long, however long it takes,
+    // but one filled in from a pattern is, since whoever typed it picked how long it takes.
+    let count = steps
+        .iter()
+        .fold(0usize, |total, (_, count, _)| total.saturating_add(*count));
+    if count > limits.max_actions {
+        return Err(ChainError::TooManyActions(count));
+    }
+    let duration = steps
+        .iter()
+        .fold(Duration::ZERO, |total, (_, count, action)| {
+            let count = (*count).min(u32::MAX as usize) as u32;
+            total.saturating_add(action.duration.saturating_mul(count))
+        });
+    // Only pattern actions are built here, the rest are borrowed from `actions`
+    let from_pattern = steps
+        .iter()
+        .any(|(_, _, action)| matches!(action, Cow::Owned(_)));
+    if (count > 1 || from_pattern) && duration > limits.max_duration {
+        return Err(ChainError::TooLong(duration));
+    }
+    // Everything a pattern matches shares its cooldown, so this goes by the action's name
+    for (index, (_, count, action)) in steps.iter().enumerate() {
+        let repeated = *count > 1
+            || steps[..index]
+                .iter()
+                .any(|(_, _, other)| other.name == action.name);
+        if repeated && action.cooldown.is_some() {
+            return Err(ChainError::RepeatsCooldown(
+                action.name.clone().unwrap_or_default(),
+            ));
+        }
+    }
+
+    Ok(steps
+        .into_iter()
+        .flat_map(|(text, count, _)| std::iter::repeat_n(text, count))
+        .collect())
+}
+
+// `left*3` or `leftx3` is `left` three times
+fn split_repeat(word: &str) -> Option<(&str, usize)> {
+    let digits = word.len() - word.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (rest, count) = word.split_at(word.len() - digits);
+    let name = rest.strip_suffix('*').or_else(|| rest.strip_suffix('x'))?;
+    match count.parse() {
+        Ok(count) if count > 0 && !name.is_empty() => Some((name, count)),
+        _ => None,
+    }
+}
+
+// The short way to write a chain, like `left*3 jump`
+pub fn describe(chain: &[String]) -> String {
+    let mut parts: Vec<String> = vec![];
+    let mut index = 0;
+    while index < chain.len() {
+        let name = &chain[index];
+        let count = chain[index..]
+            .iter()
+            .take_while(|other| *other == name)
+            .count();
+        parts.push(if count > 1 {
+            format!("{}*{}", name, count)
+        } else {
+            name.clone()
+        });
+        index += count;
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actions() -> HashMap<String, Action> {
+        parsing::parse_actions(
+            "left:\nhold a 1000\nend\nJump:\nhold space 2000\nend\nJump High:\nhold space 4000\nend\nSave [cooldown=60s]:\npress s\nend\n",
+        )
+        .unwrap_or_else(|errors| panic!("{}", errors[0]))
+    }
+
+    fn parse(message: &str) -> Result<Vec<String>, ChainError> {
+        parse_chain(
+            message,
+            &actions(),
+            &ChainLimits::default(),
+            &Matching::default(),
+        )
+    }
+
+    #[test]
+    fn expands_repeats() {
+        assert_eq!(
+            parse("left*3 Jump").unwrap(),
+            ["left", "left", "left", "Jump"]
+        );
+        assert_eq!(parse("leftx2").unwrap(), ["left", "left"]);
+    }
+
+    #[test]
+    fn prefers_the_longest_name() {
+        assert_eq!(parse("Jump High  Jump").unwrap(), ["Jump High", "Jump"]);
+    }
+
+    #[test]
+    fn anything_else_is_chat() {
+        assert_eq!(parse("left is best"), Err(ChainError::NotActions));
+        assert_eq!(parse("left*0"), Err(ChainError::NotActions));
+        assert_eq!(parse(""), Err(ChainError::NotActions));
+    }
+
+    #[test]
+    fn enforces_the_limits() {
+        assert_eq!(parse("left*6"), Err(ChainError::TooManyActions(6)));
+        assert_eq!(
+            parse("left*99999999999999999999999"),
+            Err(ChainError::NotActions)
+        );
+        assert_eq!(
+            parse("Jump High*4"),
+            Err(ChainError::TooLong(Duration::from_secs(16)))
+        );
+        // One action is never too long on its own
+        let limits = ChainLimits {
+            max_duration: Duration::from_secs(1),
+            ..ChainLimits::default()
+        };
+        assert!(parse_chain("Jump High", &actions(), &limits, &Matching::default()).is_ok());
+    }
+
+    #[test]
+    fn cooldowns_cant_repeat() {
+        assert_eq!(
+            parse("Save left Save"),
+            Err(ChainError::RepeatsCooldown("Save".to_string()))
+        );
+        assert_eq!(
+            parse("Save*2"),
+            Err(ChainError::RepeatsCooldown("Save".to_string()))
+        );
+        assert!(parse("Save left").is_ok());
+    }
+
+    #[test]
+    fn describes_runs_of_the_same_action() {
+        let chain: Vec<String> = ["left", "left", "Jump", "left"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(describe(&chain), "left*2 Jump left");
+        assert_eq!(parse(&describe(&chain)).unwrap(), chain);
+    }
+
+    #[test]
+    fn names_match_in_any_case_unless_told_not_to() {
+        let actions = actions();
+        let matching = Matching::default();
+        assert_eq!(
+            find_action("jump high", &actions, &matching),
+            Some("Jump High")
+        );
+        assert_eq!(find_action("LEFT", &actions, &matching), Some("left"));
+        let case_sensitive = Matching {
+            case_sensitive: true,
+            ..Matching::default()
+        };
+        assert_eq!(find_action("jump", &actions, &case_sensitive), None);
+        assert_eq!(find_action("Jump", &actions, &case_sensitive), Some("Jump"));
+        assert_eq!(
+            parse_chain("jump", &actions, &ChainLimits::default(), &case_sensitive),
+            Err(ChainError::NotActions)
+        );
+    }
+
+    #[test]
+    fn exact_names_beat_other_cases() {
+        let actions = parsing::parse_actions(
+            "jump:\npress a\nend\nJump:\npress b\nend\nJUMP:\npress c\nend\n",
+        )
+        .unwrap();
+        let matching = Matching::default();
+        assert_eq!(find_action("Jump", &actions, &matching), Some("Jump"));
+        // With no exact match, the first in alphabetical order wins
+        assert_eq!(find_action("jUMP", &actions, &matching), Some("JUMP"));
+    }
+
+    #[test]
+    fn near_misses_get_suggestions() {
+        let actions = actions();
+        let matching = Matching::default();
+        assert_eq!(suggest_action("jmup", &actions, &matching), Some("Jump"));
+        // Spaces and capitals are folded away before counting typos
+        assert_eq!(
+            suggest_action("  JUMP   hihg ", &actions, &matching),
+            Some("Jump High")
+        );
+        // Exact matches aren't misses, and chat isn't either
+        assert_eq!(suggest_action("Jump", &actions, &matching), None);
+        assert_eq!(
+            suggest_action("good game everyone", &actions, &matching),
+            None
+        );
+    }
+
+    #[test]
+    fn short_names_need_fewer_typos() {
+        let actions = parsing::parse_actions("up:\npress w\nend\nleft:\npress a\nend\n").unwrap();
+        let matching = Matching::default();
+        // Two typos in a two letter name is a different word
+        assert_eq!(suggest_action("ok", &actions, &matching), None);
+        assert_eq!(suggest_action("lfet", &actions, &matching), Some("left"));
+        assert_eq!(suggest_action("lef", &actions, &matching), Some("left"));
+    }
+
+    #[test]
+    fn suggestions_can_be_turned_off_or_made_strict() {
+        let actions = actions();
+        let off = Matching {
+            suggest_distance: 0,
+            ..Matching::default()
+        };
+        assert_eq!(suggest_action("jmup", &actions, &off), None);
+        let case_sensitive = Matching {
+            case_sensitive: true,
+            ..Matching::default()
+        };
+        // A different case counts as typos
+        assert_eq!(suggest_action("jUMp", &actions, &case_sensitive), None);
+        assert_eq!(
+            suggest_action("Jmup", &actions, &case_sensitive),
+            Some("Jump")
+        );
+    }
+
+    #[test]
+    fn aliases_find_their_action() {
+        let actions = parsing::parse_actions("MoveLeft | left | l:\npress a\nend\n").unwrap();
+        let matching = Matching::default();
+        assert_eq!(find_action("l", &actions, &matching), Some("MoveLeft"));
+        assert_eq!(find_action("LEFT", &actions, &matching), Some("MoveLeft"));
+        assert_eq!(
+            parse_chain("l*2 MoveLeft", &actions, &ChainLimits::default(), &matching).unwrap(),
+            ["MoveLeft", "MoveLeft", "MoveLeft"]
+        );
+        assert_eq!(suggest_action("lfet", &actions, &matching), Some("left"));
+    }
+
+    #[test]
+    fn patterns_fill_in_what_names_dont() {
+        let actions = parsing::parse_actions(
+            "Jump:\npress space\nend\n/walk (left|right) (\\d+)/ [cooldown=1s]:\nhold $1 $2\nend\n/tap (\\w+)/:\npress $1\nend\n",
+        )
+        .unwrap();
+        let limits = ChainLimits::default();
+        let matching = Matching::default();
+        let parse = |message| parse_chain(message, &actions, &limits, &matching);
+        assert_eq!(
+            parse("walk  left 500 jump").unwrap(),
+            ["walk left 500", "Jump"]
+        );
+        assert_eq!(parse("tap a*2").unwrap(), ["tap a", "tap a"]);
+        // Everything a pattern matches shares its cooldown
+        assert_eq!(
+            parse("walk left 1 walk right 1"),
+            Err(ChainError::RepeatsCooldown(
+                "/walk (left|right) (\\d+)/".to_string()
+            ))
+        );
+        assert!(matches!(
+            parse("tap sideways"),
+            Err(ChainError::InvalidArgument(error)) if error.token == "sideways"
+        ));
+        assert_eq!(parse("walk up 500"), Err(ChainError::NotActions));
+    }
+
+    #[test]
+    fn patterns_cant_wait_past_the_limit() {
+        let actions =
+            parsing::parse_actions("/walk (left|right) (\\d+)/:\nhold $1 $2\nend\n").unwrap();
+        let limits = ChainLimits::default();
+        let matching = Matching::default();
+        let parse = |message| parse_chain(message, &actions, &limits, &matching);
+        assert_eq!(parse("walk left 15000").unwrap(), ["walk left 15000"]);
+        assert_eq!(
+            parse("walk left 15001"),
+            Err(ChainError::TooLong(Duration::from_millis(15001)))
+        );
+        assert_eq!(
+            parse("walk left 99999999999"),
+            Err(ChainError::TooLong(Duration::from_millis(99999999999)))
+        );
+    }
+}
@@ -0,0 +1,933 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use toml_edit::{value, Array, DocumentMut, Item, Table};
+
+use crate::{
+    chain::{ChainLimits, Matching},
+    parsing::parse_duration,
+    players::{AgeGate, PlayerFilter},
+    queue::DropPolicy,
+    roles::{Level, RoleRef, LEVEL_NAMES},
+};
+
+// If this is set, it is used instead of the token in the config file
+pub const TOKEN_ENV_VAR: &str = "DISCORD_PLAYS_TOKEN";
+
+const DEFAULT_PREFIXES: [&str; 5] = ["d!", "D!", "dp!", "Dp!", "DP!"];
+
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(1);
+const DEFAULT_BUDGET_CAPACITY: Duration = Duration::from_secs(30);
+const DEFAULT_BUDGET_REFILL_RATE: f64 = 0.5;
+
+// What a new config.toml starts out as
+pub const DEFAULT_CONFIG: &str = r#"# Settings for Discord Plays. Restart the bot after changing them.
+
+# Your Discord bot token. You can leave this empty and set the
+# DISCORD_PLAYS_TOKEN environment variable instead.
+token = ""
+
+# What commands start with
+prefixes = ["d!", "D!", "dp!", "Dp!", "DP!"]
+
+# Roles can be written as a name, like "Mods", or as a role ID, like "759786910427381790".
+# Anyone with the Administrator permission is at least a mod, and the bot's owner is always an owner.
+[roles]
+# Can use every command, including grant and revoke
+owners = []
+# Can use every command but grant and revoke
+mods = ["Mods", "Admin"]
+# Can use the gaming commands, like start_discord_plays and reload_actions
+managers = ["Discord Plays Manager"]
+
+# Levels ("manager", "mod" or "owner") for user or role IDs.
+# grant and revoke change these.
+[grants]
+
+# What the about command shows. set_title and set_description change these.
+[about]
+title = "Sample title"
+description = "Sample description."
+
+[queue]
+# How many actions can wait to run
+max_len = 10
+# What happens to new actions when the queue is full:
+# "reject" (react with 🚫), "drop_newest" (ignore it) or "drop_oldest" (make room)
+drop_policy = "reject"
+
+# Players can send several actions in one message, like "left left jump" or "left*3 jump".
+# They go in the queue together and run one after another.
+[chains]
+# The most actions one message can send. 1 means one action per message.
+max_actions = 5
+# How long those actions can take to run, all together
+max_duration = "15s"
+
+# How long each player has to wait between any two actions
+[cooldowns]
+per_user = "1s"
+
+# Each player can save up `capacity` of input time, which fills back up at
+# `refill_rate` seconds per second. Actions cost as long as they wait.
+# "0s" turns budgets off.
+[budget]
+capacity = "30s"
+refill_rate = 0.5
+
+# How messages are matched to action names. Extra spaces are always ignored.
+[matching]
+# With this off, "jump" sends the action "Jump"
+case_sensitive = false
+# Messages this many typos away from an action get a "did you mean" reply.
+# 0 turns that off.
+suggest_distance = 2
+
+# Channels where chat can play, by guild ID.
+# add_play_channel and remove_play_channel change these.
+[play_channels]
+
+# Panels posted by controller, by channel ID.
+# Delete a message from Discord to stop using it.
+[controllers]
+
+# Messages posted by reaction_controller, by channel ID.
+# Delete a message from Discord to stop using it.
+[reaction_controllers]
+
+# Who can play. Blocked players are ignored, and with the allowlist on only the
+# users and roles in `allowed` can play (managers and above always can).
+# block_player, unblock_player and allowlist change these.
+[players]
+allowlist = false
+# User IDs, role IDs or role names
+allowed = []
+# How old someone's Discord account has to be, and how long they have to
+# have been in the server, before they can play. "0s" turns these off.
+min_account_age = "0s"
+min_membership = "0s"
+
+# Blocked user IDs, and the Unix time their block ends at (0 means never)
+[players.blocked]
+"#;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct About {
+    pub title: String,
+    pub description: String,
+}
+
+impl Default for About {
+    fn default() -> About {
+        About {
+            title: "Sample title".to_string(),
+            description: "Sample description.".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Roles {
+    pub owners: Vec<RoleRef>,
+    pub mods: Vec<RoleRef>,
+    pub managers: Vec<RoleRef>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct QueueConfig {
+    pub max_len: usize,
+    pub drop_policy: DropPolicy,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BudgetConfig {
+    pub capacity: Duration,
+    // Seconds of budget given back every second
+    pub refill_rate: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub token: String,
+    pub prefixes: Vec<String>,
+    pub roles: Roles,
+    // Levels by user or role ID
+    pub grants: HashMap<u64, Level>,
+    pub about: About,
+    pub queue: QueueConfig,
+    pub chains: ChainLimits,
+    // How long each player waits between actions
+    pub cooldown: Duration,
+    pub budget: BudgetConfig,
+    pub matching: Matching,
+    // Channel IDs by guild ID
+    pub play_channels: HashMap<u64, HashSet<u64>>,
+    // Message IDs by channel ID
+    pub controllers: HashMap<u64, HashSet<u64>>,
+    pub reaction_controllers: HashMap<u64, HashSet<u64>>,
+    pub players: PlayerFilter,
+    pub age_gate: AgeGate,
+}
+
+// The file as it is written, before it is checked
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    #[serde(default)]
+    token: String,
+    prefixes: Option<Vec<String>>,
+    #[serde(default)]
+    roles: RawRoles,
+    #[serde(default)]
+    grants: HashMap<String, String>,
+    #[serde(default)]
+    about: About,
+    #[serde(default)]
+    queue: RawQueue,
+    #[serde(default)]
+    chains: RawChains,
+    #[serde(default)]
+    cooldowns: RawCooldowns,
+    #[serde(default)]
+    budget: RawBudget,
+    #[serde(default)]
+    matching: Matching,
+    #[serde(default)]
+    play_channels: HashMap<String, Vec<u64>>,
+    #[serde(default)]
+    controllers: HashMap<String, Vec<u64>>,
+    #[serde(default)]
+    reaction_controllers: HashMap<String, Vec<u64>>,
+    #[serde(default)]
+    players: RawPlayers,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRoles {
+    #[serde(default)]
+    owners: Vec<String>,
+    mods: Vec<String>,
+    #[serde(default)]
+    managers: Vec<String>,
+}
+
+impl Default for RawRoles {
+    fn default() -> RawRoles {
+        RawRoles {
+            owners: vec![],
+            mods: vec!["Mods".to_string(), "Admin".to_string()],
+            managers: vec!["Discord Plays Manager".to_string()],
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawChains {
+    max_actions: Option<usize>,
+    max_duration: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawCooldowns {
+    per_user: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawBudget {
+    capacity: Option<String>,
+    refill_rate: Option<f64>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPlayers {
+    #[serde(default)]
+    allowlist: bool,
+    #[serde(default)]
+    allowed: Vec<String>,
+    #[serde(default)]
+    blocked: HashMap<String, u64>,
+    min_account_age: Option<String>,
+    min_membership: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawQueue {
+    #[serde(default = "default_max_len")]
+    max_len: usize,
+    #[serde(default = "default_drop_policy")]
+    drop_policy: DropPolicy,
+}
+
+impl Default for RawQueue {
+    fn default() -> RawQueue {
+        RawQueue {
+            max_len: default_max_len(),
+            drop_policy: default_drop_policy(),
+        }
+    }
+}
+
+fn default_max_len() -> usize {
+    10
+}
+
+fn default_drop_policy() -> DropPolicy {
+    DropPolicy::Reject
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(io::Error),
+    Parse(toml::de::Error),
+    // Everything wrong with a file that parsed
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(why) => write!(f, "Could not read the config file: {}", why),
+            ConfigError::Parse(why) => write!(f, "Could not parse the config file: {}", why),
+            ConfigError::Invalid(problems) => {
+                write!(f, "The config file has problems:")?;
+                for problem in problems {
+                    write!(f, "\n- {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let content = fs::read_to_string(path).map_err(ConfigError::Read)?;
+        let token = std::env::var(TOKEN_ENV_VAR).ok();
+        Config::parse(&content, token)
+    }
+
+    // `token` comes from the environment, and is used over the file's token when it isn't empty
+    pub fn parse(content: &str, token: Option<String>) -> Result<Config, ConfigError> {
+        let raw: RawConfig = toml::from_str(content).map_err(ConfigError::Parse)?;
+        let mut problems = vec![];
+
+        let token = match token {
+            Some(token) if !token.trim().is_empty() => token,
+            _ => raw.token,
+        }
+        .trim()
+        .to_string();
+        if token.is_empty() {
+            problems.push(format!(
+                "`token` is empty. Put your Discord bot token there, or set {}.",
+                TOKEN_ENV_VAR
+            ));
+        }
+
+        let prefixes = raw
+            .prefixes
+            .unwrap_or_else(|| DEFAULT_PREFIXES.iter().map(|p| p.to_string()).collect());
+        if prefixes.is_empty() {
+            problems.push("`prefixes` needs at least one prefix.".to_string());
+        }
+        if prefixes
+            .iter()
+            .any(|prefix| prefix.is_empty() || prefix.contains(char::is_whitespace))
+        {
+            problems.push("Prefixes can't be empty or contain spaces.".to_string());
+        }
+
+        for role in raw
+            .roles
+            .owners
+            .iter()
+            .chain(&raw.roles.mods)
+            .chain(&raw.roles.managers)
+        {
+            if role.trim().is_empty() {
+                problems.push("Role names can't be empty.".to_string());
+            }
+        }
+
+        if raw.queue.max_len == 0 {
+            problems.push("`queue.max_len` has to be at least 1.".to_string());
+        }
+
+        let mut chains = ChainLimits::default();
+        if let Some(max_actions) = raw.chains.max_actions {
+            if max_actions == 0 {
+                problems.push("`chains.max_actions` has to be at least 1.".to_string());
+            }
+            chains.max_actions = max_actions;
+        }
+        if let Some(max_duration) = &raw.chains.max_duration {
+            match parse_duration(max_duration) {
+                Some(max_duration) => chains.max_duration = max_duration,
+                None => problems.push(format!(
+                    "`chains.max_duration` is \"{}\", which is not a time like \"15s\" or \"1m\".",
+                    max_duration
+                )),
+            }
+        }
+
+        let mut cooldown = DEFAULT_COOLDOWN;
+        if let Some(per_user) = &raw.cooldowns.per_user {
+            match parse_duration(per_user) {
+                Some(per_user) => cooldown = per_user,
+                None => problems.push(format!(
+                    "`cooldowns.per_user` is \"{}\", which is not a time like \"1s\" or \"500ms\".",
+                    per_user
+                )),
+            }
+        }
+
+        let mut budget = BudgetConfig {
+            capacity: DEFAULT_BUDGET_CAPACITY,
+            refill_rate: DEFAULT_BUDGET_REFILL_RATE,
+        };
+        if let Some(capacity) = &raw.budget.capacity {
+            match parse_duration(capacity) {
+                Some(capacity) => budget.capacity = capacity,
+                None => problems.push(format!(
+                    "`budget.capacity` is \"{}\", which is not a time like \"30s\" or \"1m\".",
+                    capacity
+                )),
+            }
+        }
+        if let Some(refill_rate) = raw.budget.refill_rate {
+            // Budgets refill by multiplying time with this, which can't be negative or NaN
+            if refill_rate.is_finite() && refill_rate >= 0.0 {
+                budget.refill_rate = refill_rate;
+            } else {
+                problems.push(format!(
+                    "`budget.refill_rate` is {}, which has to be a number that is 0 or more.",
+                    refill_rate
+                ));
+            }
+        }
+
+        let mut grants = HashMap::new();
+        for (id, level) in raw.grants {
+            match (id.parse::<u64>(), level.parse::<Level>()) {
+                (Err(_), _) => problems.push(format!(
+                    "`grants` has \"{}\", which is not a user or role ID.",
+                    id
+                )),
+                (_, Err(_)) => problems.push(format!(
+                    "`grants` gives {} the level \"{}\", which should be one of {}.",
+                    id,
+                    level,
+                    LEVEL_NAMES.join(", ")
+                )),
+                (Ok(id), Ok(level)) => {
+                    grants.insert(id, level);
+                }
+            }
+        }
+
+        let mut play_channels = HashMap::new();
+        for (guild, channels) in raw.play_channels {
+            match guild.parse::<u64>() {
+                Ok(guild) => {
+                    if !channels.is_empty() {
+                        play_channels.insert(guild, channels.into_iter().collect());
+                    }
+                }
+                Err(_) => problems.push(format!(
+                    "`play_channels` has \"{}\", which is not a guild ID.",
+                    guild
+                )),
+            }
+        }
+
+        let mut controllers = HashMap::new();
+        for (channel, messages) in raw.controllers {
+            match channel.parse::<u64>() {
+                Ok(channel) => {
+                    if !messages.is_empty() {
+                        controllers.insert(channel, messages.into_iter().collect());
+                    }
+                }
+                Err(_) => problems.push(format!(
+                    "`controllers` has \"{}\", which is not a channel ID.",
+                    channel
+                )),
+            }
+        }
+
+        let mut reaction_controllers = HashMap::new();
+        for (channel, messages) in raw.reaction_controllers {
+            match channel.parse::<u64>() {
+                Ok(channel) => {
+                    if !messages.is_empty() {
+                        reaction_controllers.insert(channel, messages.into_iter().collect());
+                    }
+                }
+                Err(_) => problems.push(format!(
+                    "`reaction_controllers` has \"{}\", which is not a channel ID.",
+                    channel
+                )),
+            }
+        }
+
+        let mut blocked = HashMap::new();
+        for (user, until) in raw.players.blocked {
+            match user.parse::<u64>() {
+                Ok(user) => {
+                    // A time too far away to add up is as good as forever
+                    let until = match until {
+                        0 => None,
+                        seconds => UNIX_EPOCH.checked_add(Duration::from_secs(seconds)),
+                    };
+                    blocked.insert(user, until);
+                }
+                Err(_) => problems.push(format!(
+                    "`players.blocked` has \"{}\", which is not a user ID.",
+                    user
+                )),
+            }
+        }
+        if raw
+            .players
+            .allowed
+            .iter()
+            .any(|allowed| allowed.trim().is_empty())
+        {
+            problems.push("`players.allowed` can't have empty entries.".to_string());
+        }
+
+        let mut age = |name: &str, value: &Option<String>| match value {
+            Some(value) => parse_duration(value).unwrap_or_else(|| {
+                problems.push(format!(
+                    "`players.{}` is \"{}\", which is not a time like \"12h\" or \"7d\".",
+                    name, value
+                ));
+                Duration::ZERO
+            }),
+            None => Duration::ZERO,
+        };
+        let age_gate = AgeGate::new(
+            age("min_account_age", &raw.players.min_account_age),
+            age("min_membership", &raw.players.min_membership),
+        );
+
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(problems));
+        }
+        Ok(Config {
+            token,
+            prefixes,
+            roles: Roles {
+                owners: raw.roles.owners.iter().map(|r| RoleRef::new(r)).collect(),
+                mods: raw.roles.mods.iter().map(|r| RoleRef::new(r)).collect(),
+                managers: raw.roles.managers.iter().map(|r| RoleRef::new(r)).collect(),
+            },
+            grants,
+            about: raw.about,
+            queue: QueueConfig {
+                max_len: raw.queue.max_len,
+                drop_policy: raw.queue.drop_policy,
+            },
+            chains,
+            cooldown,
+            budget,
+            matching: raw.matching,
+            play_channels,
+            controllers,
+            reaction_controllers,
+            players: PlayerFilter::new(
+                blocked,
+                raw.players.allowlist,
+                raw.players
+                    .allowed
+                    .iter()
+                    .map(|a| RoleRef::new(a))
+                    .collect(),
+            ),
+            age_gate,
+        })
+    }
+}
+
+// Changes part of the config file, keeping its comments and layout
+fn edit<P: AsRef<Path>, F: FnOnce(&mut DocumentMut)>(path: P, change: F) -> io::Result<()> {
+    let content = fs::read_to_string(&path)?;
+    let mut document: DocumentMut = content
+        .parse()
+        .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))?;
+    change(&mut document);
+    fs::write(path, document.to_string())
+}
+
+fn table<'a>(document: &'a mut DocumentMut, name: &str) -> &'a mut Table {
+    if !document.contains_table(name) {
+        document.insert(name, Item::Table(Table::new()));
+    }
+    document[name].as_table_mut().unwrap()
+}
+
+pub fn save_about<P: AsRef<Path>>(path: P, about: &About) -> io::Result<()> {
+    edit(path, |document| {
+        let table = table(document, "about");
+        table["title"] = value(about.title.as_str());
+        table["description"] = value(about.description.as_str());
+    })
+}
+
+pub fn save_grants<P: AsRef<Path>>(path: P, grants: &HashMap<u64, Level>) -> io::Result<()> {
+    edit(path, |document| {
+        let table = table(document, "grants");
+        table.clear();
+        let mut grants: Vec<_> = grants.iter().collect();
+        grants.sort();
+        for (id, level) in grants {
+            table[&id.to_string()] = value(level.name());
+        }
+    })
+}
+
+pub fn save_players<P: AsRef<Path>>(path: P, players: &PlayerFilter) -> io::Result<()> {
+    edit(path, |document| {
+        let table = table(document, "players");
+        table["allowlist"] = value(players.allowlist);
+        let allowed: Array = players
+            .allowed
+            .iter()
+            .map(|allowed| allowed.config_name())
+            .collect();
+        table["allowed"] = value(allowed);
+        if !table.contains_table("blocked") {
+            table.insert("blocked", Item::Table(Table::new()));
+        }
+        let blocked = table["blocked"].as_table_mut().unwrap();
+        blocked.clear();
+        let mut users: Vec<_> = players.blocked().iter().collect();
+        users.sort_by_key(|(user, _)| **user);
+        for (user, until) in users {
+            let seconds = until
+                .and_then(|until| until.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_secs().max(1));
+            blocked[&user.to_string()] = value(seconds as i64);
+        }
+    })
+}
+
+pub fn save_play_channels<P: AsRef<Path>>(
+    path: P,
+    play_channels: &HashMap<u64, HashSet<u64>>,
+) -> io::Result<()> {
+    edit(path, |document| {
+        save_id_lists(table(document, "play_channels"), play_channels)
+    })
+}
+
+pub fn save_controllers<P: AsRef<Path>>(
+    path: P,
+    controllers: &HashMap<u64, HashSet<u64>>,
+) -> io::Result<()> {
+    edit(path, |document| {
+        save_id_lists(table(document, "controllers"), controllers)
+    })
+}
+
+pub fn save_reaction_controllers<P: AsRef<Path>>(
+    path: P,
+    reaction_controllers: &HashMap<u64, HashSet<u64>>,
+) -> io::Result<()> {
+    edit(path, |document| {
+        save_id_lists(
+            table(document, "reaction_controllers"),
+            reaction_controllers,
+        )
+    })
+}
+
+// Writes lists of IDs under other IDs, like the channels in each guild
+fn save_id_lists(table: &mut Table, lists: &HashMap<u64, HashSet<u64>>) {
+    table.clear();
+    let mut keys: Vec<_> = lists.iter().collect();
+    keys.sort_by_key(|(key, _)| **key);
+    for (key, ids) in keys {
+        let mut ids: Vec<_> = ids.iter().collect();
+        ids.sort();
+        // Discord IDs fit in an i64, which is all TOML integers can hold
+        let array: Array = ids.into_iter().map(|id| *id as i64).collect();
+        table[&key.to_string()] = value(array);
+    }
+}
+
+// Makes a new config file, carrying over the token and about text
+// from token.txt and info.json if they are still around.
+pub fn create_default<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    create_default_from(path, ".")
+}
+
+// Like `create_default`, with token.txt and info.json looked for in `old_dir`
+fn create_default_from<P: AsRef<Path>, Q: AsRef<Path>>(path: P, old_dir: Q) -> io::Result<()> {
+    let old_dir = old_dir.as_ref();
+    let mut document: DocumentMut = DEFAULT_CONFIG.parse().unwrap();
+    if let Ok(token) = fs::read_to_string(old_dir.join("token.txt")) {
+        document["token"] = value(token.trim());
+    }
+    if let Some(about) = fs::read_to_string(old_dir.join("info.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<About>(&content).ok())
+    {
+        document["about"]["title"] = value(about.title);
+        document["about"]["description"] = value(about.description);
+    }
+    fs::write(path, document.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn problems(content: &str) -> Vec<String> {
+        match Config::parse(content, Some("token".to_string())) {
+            Err(ConfigError::Invalid(problems)) => problems,
+            Err(why) => panic!("expected problems, got {}", why),
+            Ok(_) => panic!("expected problems in {:?}", content),
+        }
+    }
+
+    // An empty directory of its own for each test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("discord_plays_config_{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn the_default_config_only_needs_a_token() {
+        let config = Config::parse(DEFAULT_CONFIG, Some("token".to_string())).unwrap();
+        assert_eq!(config.prefixes, DEFAULT_PREFIXES);
+        assert_eq!(
+            config.roles.managers,
+            [RoleRef::Name("Discord Plays Manager".to_string())]
+        );
+        assert_eq!(config.queue.max_len, 10);
+        assert_eq!(config.queue.drop_policy, DropPolicy::Reject);
+        assert_eq!(config.chains.max_duration, Duration::from_secs(15));
+        assert_eq!(config.cooldown, Duration::from_secs(1));
+        assert_eq!(config.budget.capacity, Duration::from_secs(30));
+        assert_eq!(config.budget.refill_rate, 0.5);
+        assert!(!config.age_gate.is_on());
+
+        match Config::parse(DEFAULT_CONFIG, None) {
+            Err(ConfigError::Invalid(problems)) => {
+                assert_eq!(problems.len(), 1);
+                assert!(problems[0].starts_with("`token` is empty."));
+            }
+            _ => panic!("expected the empty token to be a problem"),
+        }
+    }
+
+    #[test]
+    fn the_environment_token_wins() {
+        let config = Config::parse("token = \" file \"", Some("env".to_string())).unwrap();
+        assert_eq!(config.token, "env");
+        let config = Config::parse("token = \" file \"", Some(" ".to_string())).unwrap();
+        assert_eq!(config.token, "file");
+    }
+
+    #[test]
+    fn every_invalid_value_is_reported() {
+        let mut problems = problems(
+            r#"
+prefixes = ["d!", "d !"]
+[roles]
+mods = [" "]
+[grants]
+"me" = "mod"
+"123" = "admin"
+[queue]
+max_len = 0
+[chains]
+max_actions = 0
+max_duration = "forever"
+[play_channels]
+"general" = [1]
+[controllers]
+"y" = [1]
+[reaction_controllers]
+"x" = [1]
+[players]
+allowed = [""]
+min_account_age = "7 days"
+[players.blocked]
+"someone" = 0
+"#,
+        );
+        problems.sort();
+        assert_eq!(
+            problems,
+            [
+                "Prefixes can't be empty or contain spaces.",
+                "Role names can't be empty.",
+                "`chains.max_actions` has to be at least 1.",
+                "`chains.max_duration` is \"forever\", which is not a time like \"15s\" or \"1m\".",
+                "`controllers` has \"y\", which is not a channel ID.",
+                "`grants` gives 123 the level \"admin\", which should be one of player, manager, mod, owner.",
+                "`grants` has \"me\", which is not a user or role ID.",
+                "`play_channels` has \"general\", which is not a guild ID.",
+                "`players.allowed` can't have empty entries.",
+                "`players.blocked` has \"someone\", which is not a user ID.",
+                "`players.min_account_age` is \"7 days\", which is not a time like \"12h\" or \"7d\".",
+                "`queue.max_len` has to be at least 1.",
+                "`reaction_controllers` has \"x\", which is not a channel ID.",
+            ]
+        );
+    }
+
+    #[test]
+    fn cooldowns_and_budgets_can_be_changed() {
+        let config = Config::parse(
+            "[cooldowns]\nper_user = \"0s\"\n[budget]\ncapacity = \"1m\"\nrefill_rate = 2\n",
+            Some("token".to_string()),
+        );
+        let config = config.unwrap();
+        assert_eq!(config.cooldown, Duration::ZERO);
+        assert_eq!(config.budget.capacity, Duration::from_secs(60));
+        assert_eq!(config.budget.refill_rate, 2.0);
+
+        assert_eq!(
+            problems("[cooldowns]\nper_user = \"soon\"\n[budget]\ncapacity = \"lots\"\n"),
+            [
+                "`cooldowns.per_user` is \"soon\", which is not a time like \"1s\" or \"500ms\".",
+                "`budget.capacity` is \"lots\", which is not a time like \"30s\" or \"1m\".",
+            ]
+        );
+    }
+
+    #[test]
+    fn refill_rates_have_to_be_0_or_more() {
+        for rate in &["-0.5", "nan", "inf", "-inf"] {
+            let problems = problems(&format!("[budget]\nrefill_rate = {}\n", rate));
+            assert_eq!(problems.len(), 1, "{}", rate);
+            assert!(problems[0].starts_with("`budget.refill_rate` is "));
+        }
+        let config = Config::parse("[budget]\nrefill_rate = 0\n", Some("token".to_string()));
+        assert_eq!(config.unwrap().budget.refill_rate, 0.0);
+    }
+
+    #[test]
+    fn unknown_settings_dont_parse() {
+        let error = Config::parse("[queue]\nmax_length = 5\n", Some("token".to_string()));
+        assert!(matches!(error, Err(ConfigError::Parse(_))));
+        let error = Config::parse(
+            "[queue]\ndrop_policy = \"drop_all\"\n",
+            Some("token".to_string()),
+        );
+        assert!(matches!(error, Err(ConfigError::Parse(_))));
+    }
+
+    #[test]
+    fn blocks_of_zero_last_forever() {
+        let config = Config::parse(
+            "[players.blocked]\n\"1\" = 0\n\"2\" = 60\n\"3\" = 18446744073709551615\n",
+            Some("token".to_string()),
+        );
+        // TOML integers stop at i64::MAX
+        assert!(matches!(config, Err(ConfigError::Parse(_))));
+
+        let config = Config::parse(
+            "[players.blocked]\n\"1\" = 0\n\"2\" = 60\n",
+            Some("token".to_string()),
+        )
+        .unwrap();
+        assert!(config
+            .players
+            .is_blocked(1, UNIX_EPOCH + Duration::from_secs(1 << 40)));
+        assert!(config
+            .players
+            .is_blocked(2, UNIX_EPOCH + Duration::from_secs(59)));
+        assert!(!config
+            .players
+            .is_blocked(2, UNIX_EPOCH + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn token_txt_and_info_json_are_carried_over() {
+        let dir = temp_dir("migrate");
+        fs::write(dir.join("token.txt"), "  old-token\n").unwrap();
+        fs::write(
+            dir.join("info.json"),
+            r#"{"title": "My stream", "description": "Come play"}"#,
+        )
+        .unwrap();
+        let path = dir.join("config.toml");
+        create_default_from(&path, &dir).unwrap();
+
+        let config = Config::parse(&fs::read_to_string(&path).unwrap(), None).unwrap();
+        assert_eq!(config.token, "old-token");
+        assert_eq!(config.about.title, "My stream");
+        assert_eq!(config.about.description, "Come play");
+        // The comments explaining each setting are kept
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(written.contains("# What commands start with"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_or_broken_old_files_are_skipped() {
+        let dir = temp_dir("no_migrate");
+        fs::write(dir.join("info.json"), "{ not json").unwrap();
+        let path = dir.join("config.toml");
+        create_default_from(&path, &dir).unwrap();
+
+        let config = Config::parse(
+            &fs::read_to_string(&path).unwrap(),
+            Some("token".to_string()),
+        )
+        .unwrap();
+        assert_eq!(config.about.title, About::default().title);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saved_settings_load_again() {
+        let dir = temp_dir("save");
+        let path = dir.join("config.toml");
+        create_default_from(&path, &dir).unwrap();
+
+        let mut grants = HashMap::new();
+        grants.insert(42, Level::Manager);
+        save_grants(&path, &grants).unwrap();
+        let mut play_channels = HashMap::new();
+        play_channels.insert(1, vec![10, 11].into_iter().collect());
+        save_play_channels(&path, &play_channels).unwrap();
+        save_controllers(&path, &play_channels).unwrap();
+        let mut players = PlayerFilter::default();
+        players.allowlist = true;
+        players.allow(RoleRef::new("VIP"));
+        players.block(7, None);
+        save_players(&path, &players).unwrap();
+
+        let config = Config::parse(
+            &fs::read_to_string(&path).unwrap(),
+            Some("token".to_string()),
+        )
+        .unwrap();
+        assert_eq!(config.grants, grants);
+        assert_eq!(config.play_channels, play_channels);
+        assert_eq!(config.controllers, play_channels);
+        assert!(config.players.allowlist);
+        assert_eq!(config.players.allowed, [RoleRef::Name("VIP".to_string())]);
+        assert_eq!(config.players.blocked().get(&7), Some(&None));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
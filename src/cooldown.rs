@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+// Keeps people from using actions too quickly.
+// Each user has to wait `per_user` between any two actions, and an action
+// with its own cooldown can't be used by anyone until that runs out.
+pub struct Cooldowns {
+    pub per_user: Duration,
+    // When each cooldown runs out
+    users: HashMap<u64, Instant>,
+    actions: HashMap<String, Instant>,
+}
+
+impl Cooldowns {
+    pub fn new(per_user: Duration) -> Cooldowns {
+        Cooldowns {
+            per_user,
+            users: HashMap::new(),
+            actions: HashMap::new(),
+        }
+    }
+
+    // Starts the cooldowns if neither is running.
+    // Otherwise returns how long until this user can use this action.
+    pub fn try_use(
+        &mut self,
+        user: u64,
+        action: &str,
+        action_cooldown: Option<Duration>,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        self.try_use_all(user, &[(action, action_cooldown)], now)
+    }
+
+    // Like `try_use`, for several actions sent together. The user's cooldown
+    // only starts once, and nothing starts unless every action is ready.
+    pub fn try_use_all(
+        &mut self,
+        user: u64,
+        actions: &[(&str, Option<Duration>)],
+        now: Instant,
+    ) -> Result<(), Duration> {
+        self.check(user, actions, now)?;
+        self.users.insert(user, now + self.per_user);
+        self.start_actions(actions, now);
+        // Nobody needs to remember cooldowns that are over
+        self.users.retain(|_, ready| *ready > now);
+        Ok(())
+    }
+
+    // Returns how long until this user can use all of these actions, without starting anything
+    pub fn check(
+        &self,
+        user: u64,
+        actions: &[(&str, Option<Duration>)],
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut ready = self.users.get(&user).copied().unwrap_or(now);
+        for (action, _) in actions {
+            if let Some(action_ready) = self.actions.get(*action) {
+                ready = ready.max(*action_ready);
+            }
+        }
+        if ready > now {
+            return Err(ready - now);
+        }
+        Ok(())
+    }
+
+    // Starts the actions' own cooldowns, but nobody's user cooldown.
+    // Used when a vote picks them, since the voters already waited for theirs.
+    pub fn start_actions(&mut self, actions: &[(&str, Option<Duration>)], now: Instant) {
+        for (action, action_cooldown) in actions {
+            if let Some(cooldown) = action_cooldown {
+                self.actions.insert(action.to_string(), now + *cooldown);
+            }
+        }
+        self.actions.retain(|_, ready| *ready > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn users_wait_between_their_own_actions() {
+        let start = Instant::now();
+        let mut cooldowns = Cooldowns::new(SECOND);
+        assert_eq!(cooldowns.try_use(1, "jump", None, start), Ok(()));
+        assert_eq!(
+            cooldowns.try_use(1, "left", None, start + SECOND / 4),
+            Err(SECOND * 3 / 4)
+        );
+        // Someone else isn't held up
+        assert_eq!(cooldowns.try_use(2, "jump", None, start), Ok(()));
+        assert_eq!(cooldowns.try_use(1, "left", None, start + SECOND), Ok(()));
+    }
+
+    #[test]
+    fn action_cooldowns_hold_up_everyone() {
+        let start = Instant::now();
+        let mut cooldowns = Cooldowns::new(Duration::ZERO);
+        let save = Some(SECOND * 60);
+        assert_eq!(cooldowns.try_use(1, "save", save, start), Ok(()));
+        assert_eq!(
+            cooldowns.try_use(2, "save", save, start + SECOND * 10),
+            Err(SECOND * 50)
+        );
+        assert_eq!(cooldowns.try_use(2, "jump", None, start), Ok(()));
+        assert_eq!(
+            cooldowns.try_use(2, "save", save, start + SECOND * 60),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn a_failed_try_starts_nothing() {
+        let start = Instant::now();
+        let mut cooldowns = Cooldowns::new(SECOND);
+        cooldowns.start_actions(&[("save", Some(SECOND * 5))], start);
+        assert_eq!(
+            cooldowns.try_use_all(1, &[("jump", Some(SECOND * 5)), ("save", None)], start),
+            Err(SECOND * 5)
+        );
+        // Neither the user's cooldown nor jump's started
+        assert_eq!(cooldowns.check(1, &[("jump", None)], start), Ok(()));
+    }
+
+    #[test]
+    fn chains_wait_for_the_longest_cooldown() {
+        let start = Instant::now();
+        let mut cooldowns = Cooldowns::new(SECOND);
+        cooldowns.start_actions(
+            &[("save", Some(SECOND * 5)), ("load", Some(SECOND * 2))],
+            start,
+        );
+        assert_eq!(
+            cooldowns.check(1, &[("load", None), ("save", None)], start),
+            Err(SECOND * 5)
+        );
+        // Starting the actions alone doesn't start the user's cooldown
+        assert_eq!(cooldowns.check(1, &[("jump", None)], start), Ok(()));
+    }
+}
@@ -0,0 +1,574 @@
+use std::{
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use enigo::{Enigo, Key, MouseButton};
+
+use crate::{
+    input::InputBackend,
+    parsing,
+    queue::{ActionQueue, PushResult, QueuedAction},
+};
+
+// Tells a running action to stop as soon as it can, even in the middle of a wait.
+#[derive(Default)]
+pub struct StopSignal {
+    stopped: Mutex<bool>,
+    changed: Condvar,
+}
+
+impl StopSignal {
+    pub fn new() -> StopSignal {
+        StopSignal::default()
+    }
+
+    pub fn stop(&self) {
+        *self.stopped.lock().unwrap() = true;
+        self.changed.notify_all();
+    }
+
+    pub fn reset(&self) {
+        *self.stopped.lock().unwrap() = false;
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        *self.stopped.lock().unwrap()
+    }
+
+    // Sleeps for `duration`, unless stopped first.
+    // Returns true if it was stopped.
+    pub fn wait_timeout(&self, duration: Duration) -> bool {
+        let stopped = self.stopped.lock().unwrap();
+        let (stopped, _) = self
+            .changed
+            .wait_timeout_while(stopped, duration, |stopped| !*stopped)
+            .unwrap();
+        *stopped
+    }
+}
+
+// Runs actions by sending their input to a backend.
+// By default that backend is Enigo, which presses keys on this computer.
+pub struct Executor<B: InputBackend = Enigo> {
+    backend: B,
+    actions: HashMap<String, parsing::Action>,
+    stop: Arc<StopSignal>,
+    // Everything pressed and not released yet, and whether it was pressed with `keep`
+    pressed_keys: Vec<(Key, bool)>,
+    pressed_buttons: Vec<(MouseButton, bool)>,
+    auto_release: bool,
+}
+
+impl Executor<Enigo> {
+    pub fn new(actions: HashMap<String, parsing::Action>) -> Executor<Enigo> {
+        Executor::with_backend(Enigo::new(), actions)
+    }
+}
+
+impl<B: InputBackend> Executor<B> {
+    pub fn with_backend(backend: B, actions: HashMap<String, parsing::Action>) -> Executor<B> {
+        Executor {
+            backend,
+            actions,
+            stop: Arc::new(StopSignal::new()),
+            pressed_keys: vec![],
+            pressed_buttons: vec![],
+            auto_release: true,
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    pub fn into_backend(self) -> B {
+        self.backend
+    }
+
+    pub fn actions(&self) -> &HashMap<String, parsing::Action> {
+        &self.actions
+    }
+
+    pub fn set_actions(&mut self, actions: HashMap<String, parsing::Action>) {
+        self.actions = actions;
+    }
+
+    // Stopping this cancels the running action.
+    // It stays stopped until reset, so nothing else runs in the meantime.
+    pub fn stop_signal(&self) -> Arc<StopSignal> {
+        self.stop.clone()
+    }
+
+    pub fn pressed_keys(&self) -> Vec<Key> {
+        self.pressed_keys.iter().map(|(key, _)| *key).collect()
+    }
+
+    pub fn pressed_buttons(&self) -> Vec<MouseButton> {
+        self.pressed_buttons
+            .iter()
+            .map(|(button, _)| *button)
+            .collect()
+    }
+
+    // Whether keys and buttons left down are released when an action ends.
+    // Presses marked with `keep` are never released this way.
+    pub fn set_auto_release(&mut self, auto_release: bool) {
+        self.auto_release = auto_release;
+    }
+
+    // Lets go of every key and mouse button this executor is holding down
+    pub fn release_all(&mut self) {
+        self.release_held(true);
+    }
+
+    fn release_held(&mut self, include_kept: bool) {
+        let (released, kept) = std::mem::take(&mut self.pressed_keys)
+            .into_iter()
+            .partition(|(_, keep)| include_kept || !keep);
+        self.pressed_keys = kept;
+        for (key, _) in released {
+            self.backend.key_up(key);
+        }
+        let (released, kept) = std::mem::take(&mut self.pressed_buttons)
+            .into_iter()
+            .partition(|(_, keep)| include_kept || !keep);
+        self.pressed_buttons = kept;
+        for (button, _) in released {
+            self.backend.mouse_up(button);
+        }
+    }
+
+    fn key(&mut self, button: Key, release: bool, keep: bool) {
+        self.pressed_keys.retain(|(pressed, _)| *pressed != button);
+        if !release {
+            self.backend.key_down(button);
+            self.pressed_keys.push((button, keep));
+        } else {
+            self.backend.key_up(button);
+        }
+    }
+
+    fn click(&mut self, button: MouseButton, release: bool, keep: bool) {
+        self.pressed_buttons
+            .retain(|(pressed, _)| *pressed != button);
+        if !release {
+            self.backend.mouse_down(button);
+            self.pressed_buttons.push((button, keep));
+        } else {
+            self.backend.mouse_up(button);
+        }
+    }
+
+    // Runs an action until its `end`, including any actions it calls.
+    // `action` can also be text that matches a pattern action, like `walk left 500`.
+    // Anything it left pressed is released afterwards, unless it was pressed with `keep`.
+    // Returns false if there is no action with that name, or nothing it matches.
+    pub fn run(&mut self, action: &str) -> bool {
+        let found = self.run_instructions(action);
+        if found && self.auto_release {
+            self.release_held(false);
+        }
+        found
+    }
+
+    // Called actions share their caller's pressed keys, so a called action
+    // can press something for the rest of its caller.
+    fn run_instructions(&mut self, action: &str) -> bool {
+        let used_action = match parsing::resolve(action, &self.actions) {
+            Some(used_action) => used_action.into_owned(),
+            None => return false,
+        };
+        for instruction in &used_action.instructions {
+            if self.stop.is_stopped() {
+                break;
+            }
+            match instruction {
+                parsing::Token::MouseMove {
+                    direction,
+                    distance,
+                } => match direction {
+                    parsing::Direction::Up => {
+                        self.backend.mouse_move_relative(0, -*distance);
+                    }
+                    parsing::Direction::Down => {
+                        self.backend.mouse_move_relative(0, *distance);
+                    }
+                    parsing::Direction::Left => {
+                        self.backend.mouse_move_relative(-*distance, 0);
+                    }
+                    parsing::Direction::Right => {
+                        self.backend.mouse_move_relative(*distance, 0);
+                    }
+                },
+                parsing::Token::Key {
+                    button,
+                    release,
+                    keep,
+                } => {
+                    self.key(*button, *release, *keep);
+                }
+                parsing::Token::Click {
+                    button,
+                    release,
+                    keep,
+                } => {
+                    self.click(*button, *release, *keep);
+                }
+                parsing::Token::Wait(time) => {
+                    self.backend.wait(Duration::from_millis(*time), &self.stop);
+                }
+                parsing::Token::Type(text) => {
+                    self.backend.key_sequence(text);
+                }
+                parsing::Token::Call(new_action) => {
+                    self.run_instructions(new_action);
+                }
+                parsing::Token::Screenshot => {
+                    self.backend.screenshot();
+                }
+                parsing::Token::End => {
+                    break;
+                }
+            }
+        }
+        true
+    }
+}
+
+enum ExecutorMessage {
+    SetActions(HashMap<String, parsing::Action>),
+    // Release everything, then say so
+    EmergencyStop(Sender<()>),
+}
+
+// A way to talk to an executor running on its own thread.
+// Nothing here waits for actions to finish, so it is safe to use from async code.
+#[derive(Clone)]
+pub struct ExecutorHandle {
+    sender: Sender<ExecutorMessage>,
+    queue: Arc<ActionQueue>,
+    stop: Arc<StopSignal>,
+}
+
+impl ExecutorHandle {
+    // Adds an action to the back of the queue.
+    pub fn run(&self, action: &str, requested_by: &str) -> PushResult {
+        self.run_chain(vec![action.to_string()], requested_by)
+    }
+
+    // Adds several actions to the queue as one item, so they run back to back.
+    pub fn run_chain(&self, actions: Vec<String>, requested_by: &str) -> PushResult {
+        self.queue.push(QueuedAction {
+            actions,
+            requested_by: requested_by.to_string(),
+        })
+    }
+
+    pub fn queue(&self) -> &ActionQueue {
+        &self.queue
+    }
+
+    // Takes effect before the next action starts.
+    // Returns false if the executor thread has stopped.
+    pub fn set_actions(&self, actions: HashMap<String, parsing::Action>) -> bool {
+        self.sender
+            .send(ExecutorMessage::SetActions(actions))
+            .is_ok()
+    }
+
+    // Cancels the running action, empties the queue and releases every held key and button.
+    // The returned receiver gets a message once everything has been released.
+    pub fn emergency_stop(&self) -> Receiver<()> {
+        let (done, receiver) = channel();
+        // Stop first, so the running action can't press anything else, and empty the
+        // queue before the executor hears about it, so nothing queued can start after
+        self.stop.stop();
+        self.queue.clear();
+        // The message has to be waiting before the executor is woken up,
+        // or it could wake, find nothing to do, and go back to sleep
+        let _ = self.sender.send(ExecutorMessage::EmergencyStop(done));
+        self.queue.interrupt();
+        receiver
+    }
+}
+
+// Starts an executor on a thread of its own, which runs actions from the queue one at a time.
+// The backend is made on that thread, because Enigo can't be sent between threads.
+pub fn spawn<B, F>(
+    make_backend: F,
+    actions: HashMap<String, parsing::Action>,
+    queue: Arc<ActionQueue>,
+) -> ExecutorHandle
+where
+    B: InputBackend,
+    F: FnOnce() -> B + Send + 'static,
+{
+    let (sender, receiver) = channel();
+    let stop = Arc::new(StopSignal::new());
+    let thread_queue = queue.clone();
+    let thread_stop = stop.clone();
+    thread::Builder::new()
+        .name("executor".to_string())
+        .spawn(move || {
+            let mut executor = Executor::with_backend(make_backend(), actions);
+            executor.stop = thread_stop;
+            loop {
+                let mut next = thread_queue.pop_blocking();
+                for message in receiver.try_iter() {
+                    match message {
+                        ExecutorMessage::SetActions(actions) => executor.set_actions(actions),
+                        ExecutorMessage::EmergencyStop(done) => {
+                            executor.release_all();
+                            // Whatever was popped or queued before the stop doesn't run
+                            thread_queue.clear();
+                            if next.take().is_some() {
+                                thread_queue.finish();
+                            }
+                            executor.stop.reset();
+                            let _ = done.send(());
+                        }
+                    }
+                }
+                if let Some(next) = next {
+                    for action in &next.actions {
+                        // An emergency stop ends the rest of the chain too
+                        if executor.stop.is_stopped() {
+                            break;
+                        }
+                        // A panic in one action shouldn't stop every action after it
+                        let ran = panic::catch_unwind(AssertUnwindSafe(|| executor.run(action)));
+                        if ran.is_err() {
+                            println!("Action '{}' panicked, releasing everything", action);
+                            executor.release_all();
+                        }
+                    }
+                    thread_queue.finish();
+                }
+            }
+        })
+        .expect("Could not start the executor thread");
+    ExecutorHandle {
+        sender,
+        queue,
+        stop,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        input::{InputEvent, RecordedEvent, RecordingBackend},
+        queue::DropPolicy,
+    };
+    use std::time::Instant;
+
+    // Lets a test look at what an executor on another thread has done
+    #[derive(Clone, Default)]
+    struct SharedBackend(Arc<Mutex<RecordingBackend>>);
+
+    impl InputBackend for SharedBackend {
+        fn mouse_move_relative(&mut self, x: i32, y: i32) {
+            self.0.lock().unwrap().mouse_move_relative(x, y);
+        }
+
+        fn key_down(&mut self, key: Key) {
+            self.0.lock().unwrap().key_down(key);
+        }
+
+        fn key_up(&mut self, key: Key) {
+            self.0.lock().unwrap().key_up(key);
+        }
+
+        fn mouse_down(&mut self, button: MouseButton) {
+            self.0.lock().unwrap().mouse_down(button);
+        }
+
+        fn mouse_up(&mut self, button: MouseButton) {
+            self.0.lock().unwrap().mouse_up(button);
+        }
+
+        fn key_sequence(&mut self, text: &str) {
+            self.0.lock().unwrap().key_sequence(text);
+        }
+
+        fn wait(&mut self, duration: Duration, stop: &StopSignal) {
+            self.0.lock().unwrap().wait(duration, stop);
+        }
+    }
+
+    impl SharedBackend {
+        fn events(&self) -> Vec<InputEvent> {
+            let backend = self.0.lock().unwrap();
+            backend
+                .events()
+                .iter()
+                .map(|recorded| recorded.event.clone())
+                .collect()
+        }
+
+        // Waits for the executor thread to record `count` events
+        fn wait_for(&self, count: usize) -> Vec<InputEvent> {
+            let started = Instant::now();
+            loop {
+                let events = self.events();
+                if events.len() >= count {
+                    return events;
+                }
+                assert!(
+                    started.elapsed() < Duration::from_secs(2),
+                    "the executor only recorded {:?}",
+                    events
+                );
+                thread::yield_now();
+            }
+        }
+    }
+
+    fn executor(source: &str) -> Executor<RecordingBackend> {
+        let actions = parsing::parse_actions(source).unwrap_or_else(|errors| {
+            panic!("{}", errors[0]);
+        });
+        Executor::with_backend(RecordingBackend::new(), actions)
+    }
+
+    fn at(millis: u64, event: InputEvent) -> RecordedEvent {
+        RecordedEvent {
+            at: Duration::from_millis(millis),
+            event,
+        }
+    }
+
+    #[test]
+    fn hold_releases_after_the_wait() {
+        let mut executor = executor("Jump:\nhold space 750\nend\n");
+        assert!(executor.run("Jump"));
+        assert_eq!(
+            executor.backend().events(),
+            [
+                at(0, InputEvent::KeyDown(Key::Space)),
+                at(750, InputEvent::KeyUp(Key::Space)),
+            ]
+        );
+    }
+
+    #[test]
+    fn presses_are_released_when_the_action_ends() {
+        let mut executor = executor("Grab:\npress mouse left\npress a\nwait 200\nend\n");
+        executor.run("Grab");
+        assert_eq!(
+            executor.backend().events(),
+            [
+                at(0, InputEvent::MouseDown(MouseButton::Left)),
+                at(0, InputEvent::KeyDown(Key::Layout('a'))),
+                at(200, InputEvent::KeyUp(Key::Layout('a'))),
+                at(200, InputEvent::MouseUp(MouseButton::Left)),
+            ]
+        );
+        assert!(executor.pressed_keys().is_empty());
+        assert!(executor.pressed_buttons().is_empty());
+    }
+
+    #[test]
+    fn keep_stays_pressed_until_released() {
+        let mut executor = executor("Sprint:\npress shift keep\nend\nWalk:\nrelease shift\nend\n");
+        executor.run("Sprint");
+        assert_eq!(executor.pressed_keys(), [Key::Shift]);
+        assert_eq!(
+            executor.backend().events(),
+            [at(0, InputEvent::KeyDown(Key::Shift))]
+        );
+        executor.run("Walk");
+        assert!(executor.pressed_keys().is_empty());
+        assert_eq!(
+            executor.backend_mut().take_events(),
+            [
+                at(0, InputEvent::KeyDown(Key::Shift)),
+                at(0, InputEvent::KeyUp(Key::Shift)),
+            ]
+        );
+    }
+
+    #[test]
+    fn release_all_includes_kept_keys() {
+        let mut executor = executor("Sprint:\npress shift keep\nend\n");
+        executor.run("Sprint");
+        executor.release_all();
+        assert!(executor.pressed_keys().is_empty());
+        assert_eq!(
+            executor.backend().events().last(),
+            Some(&at(0, InputEvent::KeyUp(Key::Shift)))
+        );
+    }
+
+    #[test]
+    fn auto_release_can_be_turned_off() {
+        let mut executor = executor("Hold:\npress a\nend\n");
+        executor.set_auto_release(false);
+        executor.run("Hold");
+        assert_eq!(executor.pressed_keys(), [Key::Layout('a')]);
+        assert_eq!(
+            executor.backend().events(),
+            [at(0, InputEvent::KeyDown(Key::Layout('a')))]
+        );
+    }
+
+    #[test]
+    fn called_actions_run_in_place() {
+        let mut executor = executor(
+            "Jump:\nhold space 100\nend\nCombo:\nmove left 5\nJump\nwait 50\ntype hi\nend\n",
+        );
+        executor.run("Combo");
+        assert_eq!(
+            executor.backend().events(),
+            [
+                at(0, InputEvent::MouseMove { x: -5, y: 0 }),
+                at(0, InputEvent::KeyDown(Key::Space)),
+                at(100, InputEvent::KeyUp(Key::Space)),
+                at(150, InputEvent::Type("hi".to_string())),
+            ]
+        );
+        assert_eq!(executor.backend().now(), Duration::from_millis(150));
+    }
+
+    #[test]
+    fn unknown_actions_do_nothing() {
+        let mut executor = executor("Jump:\nhold space 100\nend\n");
+        assert!(!executor.run("Fly"));
+        assert!(executor.backend().events().is_empty());
+    }
+
+    #[test]
+    fn emergency_stop_always_releases_kept_keys() {
+        let backend = SharedBackend::default();
+        let thread_backend = backend.clone();
+        let actions = parsing::parse_actions("Sprint:\npress shift keep\nend\n").unwrap();
+        let queue = Arc::new(ActionQueue::new(10, DropPolicy::Reject));
+        let handle = spawn(move || thread_backend, actions, queue);
+        for round in 0..200 {
+            assert_eq!(handle.run("Sprint", "player"), PushResult::Queued);
+            backend.wait_for(round * 2 + 1);
+            let released = handle.emergency_stop();
+            assert!(
+                released.recv_timeout(Duration::from_secs(2)).is_ok(),
+                "emergency stop {} was never handled",
+                round
+            );
+            assert_eq!(
+                backend.wait_for(round * 2 + 2).last(),
+                Some(&InputEvent::KeyUp(Key::Shift))
+            );
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::{process::Command, time::Duration};
+
+use enigo::{Enigo, Key, KeyboardControllable, MouseButton, MouseControllable};
+
+use crate::executor::StopSignal;
+
+// Everything an action can do to the computer.
+// The executor only talks to the computer through this, so it can be swapped out.
+pub trait InputBackend {
+    fn mouse_move_relative(&mut self, x: i32, y: i32);
+    fn key_down(&mut self, key: Key);
+    fn key_up(&mut self, key: Key);
+    fn mouse_down(&mut self, button: MouseButton);
+    fn mouse_up(&mut self, button: MouseButton);
+    fn key_sequence(&mut self, text: &str);
+
+    // Waits can be cut short by stopping the executor
+    fn wait(&mut self, duration: Duration, stop: &StopSignal) {
+        stop.wait_timeout(duration);
+    }
+
+    fn screenshot(&mut self) {
+        // Linux only. TODO: Make this work elsewhere
+        if let Err(why) = Command::new("gnome-screenshot").spawn() {
+            println!("Error taking screenshot: {}", why);
+        }
+    }
+}
+
+impl InputBackend for Enigo {
+    fn mouse_move_relative(&mut self, x: i32, y: i32) {
+        MouseControllable::mouse_move_relative(self, x, y);
+    }
+
+    fn key_down(&mut self, key: Key) {
+        KeyboardControllable::key_down(self, key);
+    }
+
+    fn key_up(&mut self, key: Key) {
+        KeyboardControllable::key_up(self, key);
+    }
+
+    fn mouse_down(&mut self, button: MouseButton) {
+        MouseControllable::mouse_down(self, button);
+    }
+
+    fn mouse_up(&mut self, button: MouseButton) {
+        MouseControllable::mouse_up(self, button);
+    }
+
+    fn key_sequence(&mut self, text: &str) {
+        KeyboardControllable::key_sequence(self, text);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    MouseMove { x: i32, y: i32 },
+    KeyDown(Key),
+    KeyUp(Key),
+    MouseDown(MouseButton),
+    MouseUp(MouseButton),
+    Type(String),
+    Screenshot,
+}
+
+// An event, and how long after the recording started it happened
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedEvent {
+    pub at: Duration,
+    pub event: InputEvent,
+}
+
+// Writes down every event instead of touching the computer.
+// Waiting only moves its clock forward, so it never actually sleeps.
+#[derive(Clone, Debug, Default)]
+pub struct RecordingBackend {
+    now: Duration,
+    events: Vec<RecordedEvent>,
+}
+
+impl RecordingBackend {
+    pub fn new() -> RecordingBackend {
+        RecordingBackend::default()
+    }
+
+    // How much virtual time has passed
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    pub fn events(&self) -> &[RecordedEvent] {
+        &self.events
+    }
+
+    pub fn take_events(&mut self) -> Vec<RecordedEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn record(&mut self, event: InputEvent) {
+        self.events.push(RecordedEvent {
+            at: self.now,
+            event,
+        });
+    }
+}
+
+impl InputBackend for RecordingBackend {
+    fn mouse_move_relative(&mut self, x: i32, y: i32) {
+        self.record(InputEvent::MouseMove { x, y });
+    }
+
+    fn key_down(&mut self, key: Key) {
+        self.record(InputEvent::KeyDown(key));
+    }
+
+    fn key_up(&mut self, key: Key) {
+        self.record(InputEvent::KeyUp(key));
+    }
+
+    fn mouse_down(&mut self, button: MouseButton) {
+        self.record(InputEvent::MouseDown(button));
+    }
+
+    fn mouse_up(&mut self, button: MouseButton) {
+        self.record(InputEvent::MouseUp(button));
+    }
+
+    fn key_sequence(&mut self, text: &str) {
+        self.record(InputEvent::Type(text.to_string()));
+    }
+
+    fn wait(&mut self, duration: Duration, _stop: &StopSignal) {
+        self.now += duration;
+    }
+
+    fn screenshot(&mut self) {
+        self.record(InputEvent::Screenshot);
+    }
+}
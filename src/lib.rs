@@ -0,0 +1,18 @@
+// The action language and the code that runs it.
+// The Discord bot in main.rs is just one way of feeding it actions.
+
+mod action_parsing;
+pub mod budget;
+pub mod chain;
+pub mod config;
+pub mod cooldown;
+pub mod executor;
+pub mod input;
+pub mod permissions;
+pub mod players;
+pub mod queue;
+pub mod rate;
+pub mod roles;
+pub mod voting;
+
+pub use crate::action_parsing::parsing;
@@ -1,22 +1,19 @@
 // Combination of copied from the serenity github and my own code.
 
-use serde::{Deserialize, Serialize};
-
 use std::{
     collections::{HashMap, HashSet},
     fmt::Write,
     fs::{File, OpenOptions},
-    io::{prelude::*, Write as FileWrite},
+    io::Write as FileWrite,
     path::Path,
     sync::Arc,
-    thread::{sleep, spawn as thread_spawn},
-    time::Duration,
-    process::Command,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use serenity::prelude::*;
 use serenity::{
     async_trait,
+    builder::{CreateApplicationCommands, CreateComponents, CreateEmbed},
     client::bridge::gateway::{ShardId, ShardManager},
     framework::standard::{
         help_commands,
@@ -24,22 +21,53 @@ use serenity::{
         Args, CommandGroup, CommandOptions, CommandResult, DispatchError, HelpOptions, Reason,
         StandardFramework,
     },
-    http::Http,
+    http::{Http, HttpError},
     model::{
-        channel::{Channel, Message},
+        channel::{Channel, Message, Reaction, ReactionType},
         gateway::Ready,
-        id::UserId,
+        guild,
+        id::{ChannelId, GuildId, MessageId, RoleId, UserId},
+        interactions::{
+            application_command::{ApplicationCommandInteraction, ApplicationCommandOptionType},
+            autocomplete::AutocompleteInteraction,
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
         permissions::Permissions,
     },
     utils,
 };
 use tokio::sync::Mutex;
 
-use enigo::*;
+use discord_plays::{
+    budget::Budgets,
+    chain::{self, ChainError, ChainLimits, Matching},
+    config::{self, About, Config},
+    cooldown::Cooldowns,
+    executor::{self, ExecutorHandle},
+    parsing,
+    permissions::{Access, Member},
+    players::{AgeGate, PlayerFilter, TooNew},
+    queue::{ActionQueue, PushResult},
+    rate::{Hysteresis, RateMeter},
+    roles::{Level, RoleRef, LEVEL_NAMES},
+    voting::VoteTally,
+};
+
+// Where the token, prefixes, roles and other settings are kept
+const CONFIG_PATH: &str = "config.toml";
 
-mod action_parsing;
+// How long votes are collected for in democracy mode, unless set with `set_mode`
+const DEFAULT_VOTE_WINDOW: Duration = Duration::from_secs(5);
+// The longest a vote can be set to last
+const MAX_VOTE_WINDOW: Duration = Duration::from_secs(600);
 
-pub use crate::action_parsing::parsing;
+// In automatic mode, democracy starts above this many actions per second
+// and anarchy comes back below the lower number, unless set with `set_mode`
+const DEFAULT_DEMOCRACY_ABOVE: f64 = 2.0;
+const DEFAULT_ANARCHY_BELOW: f64 = 1.0;
+// How far back chat is looked at to measure actions per second
+const PRESSURE_WINDOW: Duration = Duration::from_secs(10);
 
 // A container type is created for inserting into the Client's `data`, which
 // allows for data to be accessible across all events and framework commands, or
@@ -62,16 +90,89 @@ impl TypeMapKey for GamerModeTracker {
     type Value = bool;
 }
 
+// Anarchy runs every action as soon as it's sent,
+// democracy collects votes for a while and runs the winner.
+#[derive(Clone, Copy, PartialEq)]
+enum InputMode {
+    Anarchy,
+    Democracy,
+}
+
+struct Democracy {
+    mode: InputMode,
+    // Pick the mode from how busy chat is, instead of sticking to one
+    automatic: bool,
+    pressure: RateMeter,
+    switch: Hysteresis,
+    window: Duration,
+    // Votes for the window that is open right now, if any
+    tally: Option<VoteTally>,
+}
+
+impl Democracy {
+    // Counts an action sent in chat, and switches modes if it's automatic
+    fn record_action(&mut self, now: Instant) -> InputMode {
+        self.pressure.record(now);
+        self.refresh(now)
+    }
+
+    // Switches modes if it's automatic and chat got busier or quieter.
+    // Chat going quiet doesn't send anything, so this is also done whenever the mode is read.
+    fn refresh(&mut self, now: Instant) -> InputMode {
+        if self.automatic {
+            let rate = self.pressure.per_second(now);
+            self.mode = if self.switch.update(rate) {
+                InputMode::Democracy
+            } else {
+                InputMode::Anarchy
+            };
+        }
+        self.mode
+    }
+}
+
+struct DemocracyTracker;
+
+impl TypeMapKey for DemocracyTracker {
+    type Value = Democracy;
+}
+
+// How many actions one message can send, and for how long
+struct ChainLimitTracker;
+
+impl TypeMapKey for ChainLimitTracker {
+    type Value = ChainLimits;
+}
+
+// How messages are matched to action names
+struct MatchingTracker;
+
+impl TypeMapKey for MatchingTracker {
+    type Value = Matching;
+}
+
+struct CooldownTracker;
+
+impl TypeMapKey for CooldownTracker {
+    type Value = Cooldowns;
+}
+
+struct BudgetTracker;
+
+impl TypeMapKey for BudgetTracker {
+    type Value = Budgets;
+}
+
 struct ActionTracker;
 
 impl TypeMapKey for ActionTracker {
     type Value = HashMap<String, parsing::Action>;
 }
 
-#[derive(Deserialize, Serialize)]
-struct About {
-    title: String,
-    description: String,
+struct ExecutorContainer;
+
+impl TypeMapKey for ExecutorContainer {
+    type Value = ExecutorHandle;
 }
 
 struct CustomAbout;
@@ -80,11 +181,133 @@ impl TypeMapKey for CustomAbout {
     type Value = About;
 }
 
+// The channels in each guild where chat can play, saved in the config file
+#[derive(Default)]
+struct PlayChannels {
+    guilds: HashMap<u64, HashSet<u64>>,
+}
+
+impl PlayChannels {
+    fn contains(&self, guild: GuildId, channel: ChannelId) -> bool {
+        self.guilds
+            .get(&guild.0)
+            .is_some_and(|channels| channels.contains(&channel.0))
+    }
+
+    fn save(&self) {
+        if let Err(why) = config::save_play_channels(CONFIG_PATH, &self.guilds) {
+            println!("Could not save play channels to {}: {}", CONFIG_PATH, why);
+        }
+    }
+}
+
+struct PlayChannelTracker;
+
+impl TypeMapKey for PlayChannelTracker {
+    type Value = PlayChannels;
+}
+
+// The reaction controller messages in each channel, saved in the config file
+#[derive(Default)]
+struct ReactionControllers {
+    channels: HashMap<u64, HashSet<u64>>,
+}
+
+impl ReactionControllers {
+    fn contains(&self, channel: ChannelId, message: MessageId) -> bool {
+        self.channels
+            .get(&channel.0)
+            .is_some_and(|messages| messages.contains(&message.0))
+    }
+
+    fn remove(&mut self, channel: ChannelId, message: MessageId) {
+        if let Some(messages) = self.channels.get_mut(&channel.0) {
+            messages.remove(&message.0);
+        }
+        self.channels.retain(|_, messages| !messages.is_empty());
+    }
+
+    fn save(&self) {
+        if let Err(why) = config::save_reaction_controllers(CONFIG_PATH, &self.channels) {
+            println!(
+                "Could not save reaction controllers to {}: {}",
+                CONFIG_PATH, why
+            );
+        }
+    }
+}
+
+struct ReactionControllerTracker;
+
+impl TypeMapKey for ReactionControllerTracker {
+    type Value = ReactionControllers;
+}
+
+struct PlayerTracker;
+
+impl TypeMapKey for PlayerTracker {
+    type Value = PlayerFilter;
+}
+
+struct AgeGateTracker;
+
+impl TypeMapKey for AgeGateTracker {
+    type Value = AgeGate;
+}
+
+// The controller panels that have been posted, and the page each one is on.
+// The panels are saved in the config file, but their pages aren't.
+#[derive(Default)]
+struct Controllers {
+    panels: HashMap<MessageId, (ChannelId, usize)>,
+}
+
+impl Controllers {
+    fn load(channels: &HashMap<u64, HashSet<u64>>) -> Controllers {
+        let panels = channels
+            .iter()
+            .flat_map(|(channel, messages)| {
+                messages
+                    .iter()
+                    .map(move |message| (MessageId(*message), (ChannelId(*channel), 0)))
+            })
+            .collect();
+        Controllers { panels }
+    }
+
+    // Message IDs by channel ID, like the config file has them
+    fn channels(&self) -> HashMap<u64, HashSet<u64>> {
+        let mut channels: HashMap<u64, HashSet<u64>> = HashMap::new();
+        for (message, (channel, _)) in &self.panels {
+            channels.entry(channel.0).or_default().insert(message.0);
+        }
+        channels
+    }
+
+    fn save(&self) {
+        if let Err(why) = config::save_controllers(CONFIG_PATH, &self.channels()) {
+            println!("Could not save controllers to {}: {}", CONFIG_PATH, why);
+        }
+    }
+}
+
+struct ControllerTracker;
+
+impl TypeMapKey for ControllerTracker {
+    type Value = Controllers;
+}
+
+struct PermissionTracker;
+
+impl TypeMapKey for PermissionTracker {
+    type Value = Access;
+}
+
 struct Handler;
 
 #[async_trait]
 impl EventHandler for Handler {
-    async fn ready(&self, ctx: Context, _ready: Ready) {
+    async fn ready(&self, ctx: Context, ready: Ready) {
         let data = ctx.data.read().await;
         let actions = data
             .get::<ActionTracker>()
@@ -95,39 +318,85 @@ impl EventHandler for Handler {
             actions.len(),
             if actions.len() == 1 { "" } else { "s" }
         );
+        drop(data);
+        // Per guild, since those show up right away
+        for guild in &ready.guilds {
+            if let Err(why) = guild
+                .id()
+                .set_application_commands(&ctx.http, register_slash_commands)
+                .await
+            {
+                println!(
+                    "Could not register slash commands in guild {}: {:?}",
+                    guild.id(),
+                    why
+                );
+            }
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        match interaction {
+            Interaction::ApplicationCommand(command) => run_slash_command(&ctx, &command).await,
+            Interaction::Autocomplete(autocomplete) => {
+                autocomplete_action(&ctx, &autocomplete).await
+            }
+            Interaction::MessageComponent(component) => press_button(&ctx, &component).await,
+            _ => {}
+        }
+    }
+
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        press_reaction(&ctx, &reaction).await;
     }
 }
 
 #[group]
 #[only_in(guilds)]
-#[commands(about, commands, latency, actions)]
+#[commands(about, commands, latency, actions, budget)]
 struct General;
 
 #[group]
-// Change this to fit your specific server setup
-#[allowed_roles("Mods", "Admin")]
+// Only for the mod roles in the config file
+#[checks(Mod)]
 // Limit all commands to be guild-restricted.
 #[only_in(guilds)]
 // Summary only appears when listing multiple groups.
 #[summary = "Commands for server moderators"]
-#[commands(slow_mode, kill)]
+#[commands(slow_mode, kill, block_player, unblock_player, allowlist)]
 struct Mods;
 
 #[group]
-// Change this to fit your specific server setup
-#[allowed_roles("Mods", "Admin", "Discord Plays Manager")]
+// Only for the mod and manager roles in the config file
+#[checks(Manager)]
 #[only_in(guilds)]
 #[summary = "Commands for gaming actions"]
 #[commands(
     reload_actions,
     start_discord_plays,
     stop_discord_plays,
+    set_mode,
     set_icon,
     set_title,
-    set_description
+    set_description,
+    add_play_channel,
+    remove_play_channel,
+    controller,
+    reaction_controller,
+    queue,
+    skip,
+    clear_queue,
+    estop
 )]
 struct Gaming;
 
+#[group]
+#[checks(Owner)]
+#[only_in(guilds)]
+#[summary = "Commands for the bot's owners"]
+#[commands(grant, revoke)]
+struct Owners;
+
 #[help]
 // This replaces the information that a user can pass
 // a command-name as argument to gain specific information about it.
@@ -151,6 +420,8 @@ struct Gaming;
 #[lacking_permissions = "Hide"]
 // If the user is nothing but lacking a certain role, we just display it hence our variant is `Nothing`.
 #[lacking_role = "Nothing"]
+// Failing a check, like not having a mod role, works the same way.
+#[lacking_conditions = "Nothing"]
 // The last `enum`-variant is `Strike`, which ~~strikes~~ a command.
 #[wrong_channel = "Strike"]
 // Serenity will automatically analyse and generate a hint/tip explaining the possible
@@ -166,47 +437,13 @@ async fn my_help(
     groups: &[&'static CommandGroup],
     owners: HashSet<UserId>,
 ) -> CommandResult {
-    let mut is_admin: bool = false;
-
-    if let Some(member) = &msg.member {
-        for role in &member.roles {
-            // Change this to fit your specific server setup
-            if role
-                .to_role_cached(&context.cache)
-                .await
-                .map_or(false, |r| r.has_permission(Permissions::ADMINISTRATOR))
-                || role
-                    .to_role_cached(&context.cache)
-                    .await
-                    .expect("Invalid Role. What")
-                    .name
-                    == "Mods".to_string()
-                || role
-                    .to_role_cached(&context.cache)
-                    .await
-                    .expect("Invalid Role. What")
-                    .name
-                    == "Admin".to_string()
-            {
-                is_admin = true;
+    let level = user_level(context, msg).await;
+    let is_admin = level >= Level::Mod;
+    let is_manager = level >= Level::Manager;
 
-                break;
-            }
-        }
-    }
     // match being bad go brr
     let help_target: &str = &args.single::<String>().unwrap_or("".to_string());
-    if help_target == "slow_mode"
-        && (msg
-            .author
-            .has_role(&context.http, msg.guild_id.unwrap(), 759786910427381790)
-            .await?
-            || msg
-                .author
-                .has_role(&context.http, msg.guild_id.unwrap(), 759787598016151572)
-                .await?
-            || is_admin)
-    {
+    if help_target == "slow_mode" && is_admin {
         msg.channel_id.send_message(&context.http, |m| {
 			m.embed(|e| {
 				e.title("Slow Mode");
@@ -229,8 +466,91 @@ async fn my_help(
         msg.channel_id
             .send_message(&context.http, |m| {
                 m.embed(|e| {
-                    e.title("Kill")
-                        .field("For Mods.", "Kills the bot as soon as possible.", false);
+                    e.title("Kill").field(
+                        "For Mods.",
+                        "Releases every held key, then kills the bot as soon as possible.",
+                        false,
+                    );
+                    e
+                });
+
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "block_player" && is_admin {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Block Player").field(
+                        "For Mods.",
+                        "Stops someone from playing: `block_player @someone [time]`.\nTimes look like `30m`, `2h` or `7d`. Without one, the block lasts until `unblock_player`.",
+                        false,
+                    );
+                    e
+                });
+
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "unblock_player" && is_admin {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Unblock Player").field(
+                        "For Mods.",
+                        "Lets a blocked player play again: `unblock_player @someone`.",
+                        false,
+                    );
+                    e
+                });
+
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "allowlist" && is_admin {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Allowlist").field(
+                        "For Mods.",
+                        "While the allowlist is on, only the users and roles on it can play (managers and above always can).\n`allowlist on`, `allowlist off`, `allowlist add @someone`, `allowlist remove @someone`, or just `allowlist` to see it.",
+                        false,
+                    );
+                    e
+                });
+
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "budget" {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Budget").field(
+                        "For Anyone",
+                        "Shows how much input time you have left.\nEvery action uses up some of it (usually as long as the action takes), and it slowly fills back up.",
+                        false,
+                    );
+                    e
+                });
+
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "actions" {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Actions").field(
+                        "For Anyone",
+                        "Lists every action. Give it an action's name to see its cooldown, cost and who can use it.\nAlso a slash command, `/actions`, which suggests names as you type.",
+                        false,
+                    );
                     e
                 });
 
@@ -255,7 +575,7 @@ async fn my_help(
             .await
             .unwrap();
     // set_title, set_description
-    } else if help_target == "start_discord_plays" && is_admin {
+    } else if help_target == "start_discord_plays" && is_manager {
         msg.channel_id.send_message(&context.http, |m| {
 			m.embed(|e| {
 				e.title("Start Discord Plays")
@@ -265,7 +585,23 @@ async fn my_help(
 			
 			m
 		}).await.unwrap();
-    } else if help_target == "stop_discord_plays" && is_admin {
+    } else if help_target == "set_mode" && is_manager {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Set Mode").field(
+                        "For Mods.",
+                        "`set_mode anarchy` runs every action as soon as it is sent.\n`set_mode democracy [seconds]` collects votes for a few seconds (5 by default) and runs the action with the most votes.\n`set_mode auto [above] [below]` switches to democracy when chat sends more than `above` actions per second (2 by default), and back to anarchy below `below` (1 by default).",
+                        false,
+                    );
+                    e
+                });
+
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "stop_discord_plays" && is_manager {
         msg.channel_id
             .send_message(&context.http, |m| {
                 m.embed(|e| {
@@ -281,7 +617,7 @@ async fn my_help(
             })
             .await
             .unwrap();
-    } else if help_target == "reload_actions" && is_admin {
+    } else if help_target == "reload_actions" && is_manager {
         msg.channel_id
             .send_message(&context.http, |m| {
                 m.embed(|e| {
@@ -297,13 +633,13 @@ async fn my_help(
             })
             .await
             .unwrap();
-    } else if help_target == "set_icon" && is_admin {
+    } else if help_target == "set_icon" && is_manager {
         msg.channel_id
             .send_message(&context.http, |m| {
                 m.embed(|e| {
                     e.title("Set Icon").field(
                         "For Mods.",
-                        "Sets the bot's avatar and the thumbnail in the about embed.",
+                        "Sets the bot's avatar and the thumbnail in the about embed.\nUpload the image with the command, or give `/set_icon` a link to it.",
                         false,
                     );
                     e
@@ -313,7 +649,7 @@ async fn my_help(
             })
             .await
             .unwrap();
-    } else if help_target == "set_title" && is_admin {
+    } else if help_target == "set_title" && is_manager {
         msg.channel_id
             .send_message(&context.http, |m| {
                 m.embed(|e| {
@@ -329,7 +665,7 @@ async fn my_help(
             })
             .await
             .unwrap();
-    } else if help_target == "set_description" && is_admin {
+    } else if help_target == "set_description" && is_manager {
         msg.channel_id
             .send_message(&context.http, |m| {
                 m.embed(|e| {
@@ -345,59 +681,223 @@ async fn my_help(
             })
             .await
             .unwrap();
-    } else {
-        let help_data = help_commands::create_customised_help_data(
-            context,
-            msg,
-            &args,
-            &groups,
-            &owners,
-            help_options,
-        )
-        .await;
-        let mut formatted_data = HashMap::new();
-        msg.channel_id.send_message(&context.http, |m| -> &mut serenity::builder::CreateMessage {
-			m.embed(|e| -> &mut serenity::builder::CreateEmbed {
-				e.description("If you need more info on a command, type `help [command]`\n~~Strikethrough commands~~ are unavailable because they require certain conditions, or are limited to server messages.");
-				match help_data {
-					help_commands::CustomisedHelpData::GroupedCommands {
-						ref groups,
-						help_description: _,
-					} => for x in groups { formatted_data.insert(x.name, x.command_names.join("\n")); }
-				    _ => {}
-				}
-				e.field("General", &formatted_data["General"], true);
-				if is_admin {
-					e.field("Mods", &formatted_data["Mods"], true);
-				}
-				e.field("Gaming", &formatted_data["Gaming"], true);
-				e
-			});
-		
-			m
-		}).await?;
-    }
-    Ok(())
-}
+    } else if (help_target == "estop" || help_target == "panic") && is_manager {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Emergency Stop").field(
+                        "For Mods.",
+                        "Stops Discord Plays, cancels the running action and every queued action,\nand releases every key and mouse button the bot is holding down.",
+                        false,
+                    );
+                    e
+                });
 
-#[hook]
-async fn before(ctx: &Context, _msg: &Message, command_name: &str) -> bool {
-    // Increment the number of times this command has been run once. If
-    // the command's name does not exist in the counter, add a default
-    // value of 0.
-    let mut data = ctx.data.write().await;
-    let counter = data
-        .get_mut::<CommandCounter>()
-        .expect("Expected CommandCounter in TypeMap.");
-    let entry = counter.entry(command_name.to_string()).or_insert(0);
-    *entry += 1;
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "add_play_channel" && is_manager {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Add Play Channel").field(
+                        "For Mods.",
+                        "Lets chat play in a channel. Without a channel, uses the one you are typing in.\nActions sent anywhere else are ignored.",
+                        false,
+                    );
+                    e
+                });
 
-    true // if `before` returns false, command processing doesn't happen.
-}
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "remove_play_channel" && is_manager {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Remove Play Channel").field(
+                        "For Mods.",
+                        "Stops chat from playing in a channel. Without a channel, uses the one you are typing in.",
+                        false,
+                    );
+                    e
+                });
 
-#[hook]
-async fn after(_ctx: &Context, _msg: &Message, command_name: &str, command_result: CommandResult) {
-    match command_result {
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "controller" && is_manager {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Controller").field(
+                        "For Managers.",
+                        "Posts a panel with a button for every action, for playing without typing. Pressing one counts the same as typing the action.\nWith more than 25 actions the panel has pages. It updates itself after `reload_actions`.",
+                        false,
+                    );
+                    e
+                });
+
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "reaction_controller" && is_manager {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Reaction Controller").field(
+                        "For Managers.",
+                        "Posts a message with a reaction for every action that has an `emoji` in actions.txt. Reacting counts the same as typing the action, and the reaction is taken away so it can be used again.\nIt keeps working after a restart, and updates itself after `reload_actions`. Delete the message to stop using it.",
+                        false,
+                    );
+                    e
+                });
+
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "grant" && level >= Level::Owner {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Grant").field(
+                        "For Owners.",
+                        "Gives a user or role a level: `grant @someone mod`.\nLevels are manager (gaming commands), mod (every command but these two) and owner (everything).",
+                        false,
+                    );
+                    e
+                });
+
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "revoke" && level >= Level::Owner {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Revoke").field(
+                        "For Owners.",
+                        "Takes away a level given with `grant`.\nLevels from roles in config.toml stay.",
+                        false,
+                    );
+                    e
+                });
+
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "queue" && is_manager {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Queue").field(
+                        "For Mods.",
+                        "Shows the action that is running and the actions waiting to run.",
+                        false,
+                    );
+                    e
+                });
+
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "skip" && is_manager {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Skip").field(
+                        "For Mods.",
+                        "Removes the next action from the queue before it runs.",
+                        false,
+                    );
+                    e
+                });
+
+                m
+            })
+            .await
+            .unwrap();
+    } else if help_target == "clear_queue" && is_manager {
+        msg.channel_id
+            .send_message(&context.http, |m| {
+                m.embed(|e| {
+                    e.title("Clear Queue").field(
+                        "For Mods.",
+                        "Removes every action waiting in the queue.",
+                        false,
+                    );
+                    e
+                });
+
+                m
+            })
+            .await
+            .unwrap();
+    } else {
+        let help_data = help_commands::create_customised_help_data(
+            context,
+            msg,
+            &args,
+            groups,
+            &owners,
+            help_options,
+        )
+        .await;
+        let mut formatted_data = HashMap::new();
+        msg.channel_id.send_message(&context.http, |m| -> &mut serenity::builder::CreateMessage {
+			m.embed(|e| -> &mut serenity::builder::CreateEmbed {
+				e.description("If you need more info on a command, type `help [command]`\n~~Strikethrough commands~~ are unavailable because they require certain conditions, or are limited to server messages.");
+				if let help_commands::CustomisedHelpData::GroupedCommands {
+					ref groups,
+					help_description: _,
+				} = help_data {
+					for x in groups { formatted_data.insert(x.name, x.command_names.join("\n")); }
+				}
+				e.field("General", &formatted_data["General"], true);
+				if is_admin {
+					e.field("Mods", &formatted_data["Mods"], true);
+				}
+				e.field("Gaming", &formatted_data["Gaming"], true);
+				if level >= Level::Owner {
+					if let Some(commands) = formatted_data.get("Owners") {
+						e.field("Owners", commands, true);
+					}
+				}
+				e
+			});
+		
+			m
+		}).await?;
+    }
+    Ok(())
+}
+
+#[hook]
+async fn before(ctx: &Context, _msg: &Message, command_name: &str) -> bool {
+    // Increment the number of times this command has been run once. If
+    // the command's name does not exist in the counter, add a default
+    // value of 0.
+    let mut data = ctx.data.write().await;
+    let counter = data
+        .get_mut::<CommandCounter>()
+        .expect("Expected CommandCounter in TypeMap.");
+    let entry = counter.entry(command_name.to_string()).or_insert(0);
+    *entry += 1;
+
+    true // if `before` returns false, command processing doesn't happen.
+}
+
+#[hook]
+async fn after(_ctx: &Context, _msg: &Message, command_name: &str, command_result: CommandResult) {
+    match command_result {
         Ok(()) => println!("Processed command '{}'", command_name),
         Err(why) => println!("Command '{}' returned error {:?}", command_name, why),
     }
@@ -408,88 +908,454 @@ async fn unknown_command(_ctx: &Context, _msg: &Message, unknown_command_name: &
     println!("Could not find command named '{}'", unknown_command_name);
 }
 
+// Someone asking for an action, from a chat message or a controller button
+struct Input {
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    user: UserId,
+    // Shown in the queue
+    name: String,
+    role_ids: Vec<RoleId>,
+    // When they joined the server, if that is known
+    joined: Option<SystemTime>,
+}
+
+impl Input {
+    fn from_message(msg: &Message) -> Input {
+        Input::new(
+            msg.guild_id,
+            msg.channel_id,
+            &msg.author,
+            msg.member.as_ref().map(|member| &member.roles[..]),
+            msg.member
+                .as_ref()
+                .and_then(|member| member.joined_at)
+                .map(|joined| joined.timestamp_millis()),
+        )
+    }
+
+    fn from_member(
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        user: &serenity::model::user::User,
+        member: Option<&guild::Member>,
+    ) -> Input {
+        Input::new(
+            guild_id,
+            channel_id,
+            user,
+            member.map(|member| &member.roles[..]),
+            member
+                .and_then(|member| member.joined_at)
+                .map(|joined| joined.timestamp_millis()),
+        )
+    }
+
+    fn from_reaction(reaction: &Reaction, user: &serenity::model::user::User) -> Input {
+        Input::new(
+            reaction.guild_id,
+            reaction.channel_id,
+            user,
+            reaction.member.as_ref().map(|member| &member.roles[..]),
+            reaction
+                .member
+                .as_ref()
+                .and_then(|member| member.joined_at)
+                .map(|joined| joined.timestamp_millis()),
+        )
+    }
+
+    fn new(
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        user: &serenity::model::user::User,
+        role_ids: Option<&[RoleId]>,
+        // In milliseconds since the Unix epoch
+        joined: Option<i64>,
+    ) -> Input {
+        Input {
+            guild_id,
+            channel_id,
+            user: user.id,
+            name: user.name.clone(),
+            role_ids: role_ids.unwrap_or_default().to_vec(),
+            joined: joined.map(from_unix_millis),
+        }
+    }
+
+    async fn roles(&self, ctx: &Context) -> (Vec<(u64, String)>, bool) {
+        roles_of(ctx, self.guild_id, self.user, &self.role_ids).await
+    }
+}
+
+fn from_unix_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+// What happened to some input
+enum Played {
+    // Queued, or counted as a vote
+    Sent,
+    // Not an action here right now
+    Ignored,
+    // Blocked, or not on the allowlist
+    NotAllowed,
+    // Their account or membership is too new. `first_time` is true once per player.
+    TooNew { reason: String, first_time: bool },
+    Locked,
+    OutOfBudget,
+    OnCooldown,
+    QueueFull,
+    // Too many actions at once, or ones that would take too long
+    TooMuch(ChainError),
+    // Not an action, but close to this one
+    Misspelled(String),
+}
+
 #[hook]
 async fn normal_message(ctx: &Context, msg: &Message) {
     println!("{}", msg.content);
+    match play(ctx, &Input::from_message(msg), &msg.content).await {
+        Played::Sent | Played::Ignored | Played::NotAllowed => {}
+        Played::TooNew { reason, first_time } => {
+            if first_time {
+                let _ = msg.reply(&ctx.http, reason).await;
+            }
+        }
+        Played::Locked => {
+            let _ = msg.react(ctx, '🔒').await;
+        }
+        Played::OutOfBudget => {
+            let _ = msg.react(ctx, '🪫').await;
+        }
+        Played::OnCooldown => delay_action(ctx, msg).await,
+        Played::QueueFull => {
+            let _ = msg.react(ctx, '🚫').await;
+        }
+        // Only a reply can say what was wrong with it
+        Played::TooMuch(why @ ChainError::InvalidArgument(_)) => {
+            let _ = msg.reply(&ctx.http, format!("{}.", why)).await;
+        }
+        Played::TooMuch(_) => {
+            let _ = msg.react(ctx, '📏').await;
+        }
+        Played::Misspelled(name) => {
+            let _ = msg
+                .reply(&ctx.http, format!("Did you mean {}?", name))
+                .await;
+        }
+    }
+}
+
+// Each different action in a chain, and what all of them cost together.
+// Everything a pattern action matches counts as that action.
+fn chain_actions(
+    chain: &[String],
+    actions: &HashMap<String, parsing::Action>,
+) -> (Vec<(String, parsing::Action)>, Duration) {
+    let mut used: Vec<(String, parsing::Action)> = vec![];
+    let mut cost = Duration::ZERO;
+    for text in chain {
+        if let Some(action) = parsing::resolve(text, actions) {
+            cost = cost.saturating_add(action.cost);
+            let name = action.name.clone().unwrap_or_else(|| text.clone());
+            if !used.iter().any(|(used_name, _)| *used_name == name) {
+                used.push((name, action.into_owned()));
+            }
+        }
+    }
+    (used, cost)
+}
+
+// Runs every check on some input, then queues it or counts it as a vote.
+// `text` can be one action, or several like `left*3 jump`.
+async fn play(ctx: &Context, input: &Input, text: &str) -> Played {
     let data = ctx.data.read().await;
-    let mode = data
+    // Only input in a play channel can be actions
+    let in_play_channel = match input.guild_id {
+        Some(guild_id) => data
+            .get::<PlayChannelTracker>()
+            .expect("Couldn't find play channels in TypeMap.")
+            .contains(guild_id, input.channel_id),
+        None => false,
+    };
+    if !in_play_channel {
+        return Played::Ignored;
+    }
+    if !*data
         .get::<GamerModeTracker>()
-        .expect("Couldn't find Game mode tracker in TypeMap.");
+        .expect("Couldn't find Game mode tracker in TypeMap.")
+    {
+        return Played::Ignored;
+    }
     let actions = data
         .get::<ActionTracker>()
         .expect("Couldn't find actions in TypeMap.");
+    let limits = data
+        .get::<ChainLimitTracker>()
+        .expect("Couldn't find chain limits in TypeMap.");
+    let matching = data
+        .get::<MatchingTracker>()
+        .expect("Couldn't find matching in TypeMap.");
+
+    let chain = chain::parse_chain(text, actions, limits, matching);
+    let mut suggestion = None;
+    let (used, cost) = match &chain {
+        Ok(chain) => chain_actions(chain, actions),
+        Err(ChainError::NotActions) => match chain::suggest_action(text, actions, matching) {
+            Some(name) => {
+                suggestion = Some(name.to_string());
+                (vec![], Duration::ZERO)
+            }
+            None => return Played::Ignored,
+        },
+        Err(_) => (vec![], Duration::ZERO),
+    };
+    drop(data);
 
-    if *mode {
-        let used_action = msg.content.clone();
-        if actions.contains_key(&used_action) {
-            let thread_actions = actions.clone();
-            thread_spawn(move || {
-                let used_action_clone = &used_action.clone();
-                fn run_action(actions: &HashMap<String, parsing::Action>, action: String) {
-                    let mut enigo = Enigo::new();
-                    let used_action = actions[&action].clone();
-                    let mut action_index: usize = 0;
-                    loop {
-                        match &used_action.instructions[action_index] {
-                            parsing::Token::MouseMove {
-                                direction,
-                                distance,
-                            } => match direction {
-                                parsing::Direction::Up => {
-                                    enigo.mouse_move_relative(0, -*distance);
-                                }
-                                parsing::Direction::Down => {
-                                    enigo.mouse_move_relative(0, *distance);
-                                }
-                                parsing::Direction::Left => {
-                                    enigo.mouse_move_relative(-*distance, 0);
-                                }
-                                parsing::Direction::Right => {
-                                    enigo.mouse_move_relative(*distance, 0);
-                                }
-                            },
-                            parsing::Token::Key { button, release } => {
-                                if !release {
-                                    enigo.key_down(*button);
-                                } else {
-                                    enigo.key_up(*button);
-                                }
-                            }
-                            parsing::Token::Click { button, release } => {
-                                if !release {
-                                    enigo.mouse_down(*button);
-                                } else {
-                                    enigo.mouse_up(*button);
-                                }
-                            }
-                            parsing::Token::Wait(time) => {
-                                sleep(Duration::from_millis(*time));
-                            }
-                            parsing::Token::Type(text) => {
-                                enigo.key_sequence(&text);
-                            }
-                            parsing::Token::Call(new_action) => {
-                                run_action(actions, new_action.to_string());
-                            }
-                            parsing::Token::Screenshot => {
-                                // Linux only. TODO: Make this work elsewhere
-                                Command::new("gnome-screenshot")
-                                .spawn()
-                                .expect("Error taking screenshot");
-                            }
-                            parsing::Token::End => {
-                                break;
-                            }
-                        }
-                        action_index += 1;
-                    }
-                }
-                run_action(&thread_actions, used_action_clone.to_string());
-            })
-            .join()
-            .expect("Error running action");
+    if !can_play(ctx, input).await {
+        return Played::NotAllowed;
+    }
+    if let Err(too_new) = passes_age_gate(ctx, input).await {
+        return too_new;
+    }
+    // Only players who could have sent it get told about a typo
+    if let Some(name) = suggestion {
+        return Played::Misspelled(name);
+    }
+    let chain = match chain {
+        Ok(chain) => chain,
+        Err(why) => return Played::TooMuch(why),
+    };
+    if used.iter().any(|(_, action)| action.is_restricted()) {
+        let (roles, administrator) = input.roles(ctx).await;
+        let member = Member {
+            id: input.user.0,
+            roles: &roles,
+            administrator,
+        };
+        let data = ctx.data.read().await;
+        let access = data
+            .get::<PermissionTracker>()
+            .expect("Couldn't find permissions in TypeMap.");
+        if !used
+            .iter()
+            .all(|(_, action)| access.can_use(&member, action.level, &action.roles))
+        {
+            return Played::Locked;
+        }
+    }
+
+    let now = Instant::now();
+    let mut data = ctx.data.write().await;
+    data.get_mut::<DemocracyTracker>()
+        .expect("Couldn't find democracy tracker in TypeMap.")
+        .refresh(now);
+    // Check the budget first, so actions that can't be paid for don't start a cooldown
+    if !data
+        .get::<BudgetTracker>()
+        .expect("Couldn't find budgets in TypeMap.")
+        .can_afford(input.user.0, cost, now)
+    {
+        return Played::OutOfBudget;
+    }
+    let cooldowns: Vec<(&str, Option<Duration>)> = used
+        .iter()
+        .map(|(name, action)| (name.as_str(), action.cooldown))
+        .collect();
+    if data
+        .get::<CooldownTracker>()
+        .expect("Couldn't find cooldowns in TypeMap.")
+        .check(input.user.0, &cooldowns, now)
+        .is_err()
+    {
+        return Played::OnCooldown;
+    }
+    let input_mode = data
+        .get_mut::<DemocracyTracker>()
+        .expect("Couldn't find democracy tracker in TypeMap.")
+        .record_action(now);
+    match input_mode {
+        InputMode::Anarchy => {
+            // Queued before anything is charged, so actions the queue turns away
+            // don't cost anything or lock anyone out
+            let played = queue_action(&data, input, chain);
+            if let Played::Sent = played {
+                let _ = data
+                    .get_mut::<CooldownTracker>()
+                    .expect("Couldn't find cooldowns in TypeMap.")
+                    .try_use_all(input.user.0, &cooldowns, now);
+                let _ = data
+                    .get_mut::<BudgetTracker>()
+                    .expect("Couldn't find budgets in TypeMap.")
+                    .try_spend(input.user.0, cost, now);
+            }
+            played
+        }
+        // A vote only starts the voter's own cooldown. The actions' cooldowns and
+        // costs are charged in `close_vote`, and only if they win.
+        InputMode::Democracy => {
+            let _ = data
+                .get_mut::<CooldownTracker>()
+                .expect("Couldn't find cooldowns in TypeMap.")
+                .try_use_all(input.user.0, &[], now);
+            drop(data);
+            // Everyone who sent the same actions votes for the same thing
+            cast_vote(ctx, input, &chain::describe(&chain)).await;
+            Played::Sent
+        }
+    }
+}
+
+// The executor thread presses the keys, so this returns right away
+fn queue_action(data: &TypeMap, input: &Input, chain: Vec<String>) -> Played {
+    let executor = data
+        .get::<ExecutorContainer>()
+        .expect("Couldn't find executor in TypeMap.");
+    let name = chain::describe(&chain);
+    match executor.run_chain(chain, &input.name) {
+        PushResult::Queued => {}
+        PushResult::DroppedOldest(oldest) => {
+            println!(
+                "Queue full, dropped '{}' from {}",
+                oldest.name(),
+                oldest.requested_by
+            );
+        }
+        PushResult::DroppedNewest => {
+            println!("Queue full, dropped '{}' from {}", name, input.name);
+        }
+        PushResult::Rejected => return Played::QueueFull,
+    }
+    Played::Sent
+}
+
+async fn cast_vote(ctx: &Context, input: &Input, name: &str) {
+    let mut data = ctx.data.write().await;
+    let democracy = data
+        .get_mut::<DemocracyTracker>()
+        .expect("Couldn't find democracy tracker in TypeMap.");
+    // The first vote opens a new window
+    if democracy.tally.is_none() {
+        tokio::spawn(close_vote(ctx.clone(), input.channel_id, democracy.window));
+    }
+    democracy
+        .tally
+        .get_or_insert_with(VoteTally::new)
+        .vote(input.user.0, name);
+}
+
+async fn close_vote(ctx: Context, channel_id: ChannelId, window: Duration) {
+    tokio::time::sleep(window).await;
+    let mut data = ctx.data.write().await;
+    let tally = match data
+        .get_mut::<DemocracyTracker>()
+        .expect("Couldn't find democracy tracker in TypeMap.")
+        .tally
+        .take()
+    {
+        Some(tally) => tally,
+        None => return,
+    };
+    // Votes don't count if Discord Plays was stopped in the meantime
+    if !*data
+        .get::<GamerModeTracker>()
+        .expect("Couldn't find Game mode tracker in TypeMap.")
+    {
+        return;
+    }
+    let executor = data
+        .get::<ExecutorContainer>()
+        .expect("Couldn't find executor in TypeMap.")
+        .clone();
+    drop(data);
+
+    let winner = match tally.winner(&mut rand::thread_rng()) {
+        Some(winner) => winner,
+        None => return,
+    };
+    let tied = tally.leaders().len() > 1;
+    // The votes are for the short way of writing the actions, like `left*3 jump`
+    let chain = {
+        let data = ctx.data.read().await;
+        chain::parse_chain(
+            &winner,
+            data.get::<ActionTracker>()
+                .expect("Couldn't find actions in TypeMap."),
+            data.get::<ChainLimitTracker>()
+                .expect("Couldn't find chain limits in TypeMap."),
+            data.get::<MatchingTracker>()
+                .expect("Couldn't find matching in TypeMap."),
+        )
+    };
+    let chain = match chain {
+        Ok(chain) => chain,
+        // The actions were reloaded while people were voting
+        Err(why) => {
+            println!(
+                "Democracy picked '{}', which can't run now: {}",
+                winner, why
+            );
+            return;
+        }
+    };
+    // Only the winner's voters pay for it, and only it starts its cooldowns
+    {
+        let now = Instant::now();
+        let mut data = ctx.data.write().await;
+        let (used, cost) = chain_actions(
+            &chain,
+            data.get::<ActionTracker>()
+                .expect("Couldn't find actions in TypeMap."),
+        );
+        let cooldowns: Vec<(&str, Option<Duration>)> = used
+            .iter()
+            .map(|(name, action)| (name.as_str(), action.cooldown))
+            .collect();
+        data.get_mut::<CooldownTracker>()
+            .expect("Couldn't find cooldowns in TypeMap.")
+            .start_actions(&cooldowns, now);
+        let budgets = data
+            .get_mut::<BudgetTracker>()
+            .expect("Couldn't find budgets in TypeMap.");
+        for voter in tally.voters_for(&winner) {
+            budgets.spend(voter, cost, now);
         }
     }
+    let rejected = executor.run_chain(chain, "Democracy") == PushResult::Rejected;
+
+    let mut list = String::new();
+    for (action, count) in tally.results() {
+        list += &format!(
+            "{}: {} vote{}\n",
+            action,
+            count,
+            if count == 1 { "" } else { "s" }
+        );
+    }
+    let _ = channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title(format!("Democracy picked {}", winner));
+                e.description(list);
+                e.footer(|f| {
+                    f.text(format!(
+                        "{} voter{}{}{}",
+                        tally.voters(),
+                        if tally.voters() == 1 { "" } else { "s" },
+                        if tied { ", tie broken randomly" } else { "" },
+                        if rejected {
+                            ", but the queue is full"
+                        } else {
+                            ""
+                        }
+                    ))
+                });
+                e
+            });
+            m
+        })
+        .await;
 }
 
 #[hook]
@@ -540,25 +1406,18 @@ fn _dispatch_error_no_macro<'fut>(
 
 #[tokio::main]
 async fn main() {
-    let mut token: String = String::new();
-    {
-        // Configure the client with your Discord bot token in the file.
-        let mut file: File;
-        if Path::new("token.txt").exists() {
-            file = OpenOptions::new().read(true).open("token.txt").unwrap();
-        } else {
-            {
-                OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .open("token.txt")
-                    .unwrap();
-            }
-            panic!("Put your Discord Bot Token in the token.txt file");
-        }
-        file.read_to_string(&mut token).unwrap();
-        token = token.replace("\n", "");
+    if !Path::new(CONFIG_PATH).exists() {
+        config::create_default(CONFIG_PATH).expect("Could not create config.toml");
+        println!("Created {}. Check the settings in it.", CONFIG_PATH);
     }
+    let config = match Config::load(CONFIG_PATH) {
+        Ok(config) => config,
+        Err(why) => {
+            println!("{}", why);
+            std::process::exit(1);
+        }
+    };
+    let token = config.token.clone();
     let http = Http::new_with_token(&token);
 
     // We will fetch your bot's owners and id
@@ -572,12 +1431,14 @@ async fn main() {
         Err(why) => panic!("Could not access application info: {:?}", why),
     };
 
+    let bot_owners = owners.iter().map(|owner| owner.0).collect();
+
     // Create the framework
     let framework = StandardFramework::new()
         .configure(|c| {
             c.with_whitespace(true)
                 .on_mention(Some(bot_id))
-                .prefixes(["d!", "D!", "dp!", "Dp!", "DP!"])
+                .prefixes(&config.prefixes)
                 // In this case, if "," would be first, a message would never
                 // be delimited at ", ", forcing you to trim your arguments if you
                 // want to avoid whitespaces at the start of each.
@@ -621,36 +1482,89 @@ async fn main() {
         .help(&MY_HELP)
         .group(&GENERAL_GROUP)
         .group(&MODS_GROUP)
-        .group(&GAMING_GROUP);
+        .group(&GAMING_GROUP)
+        .group(&OWNERS_GROUP);
     let mut client = Client::builder(&token)
+        .application_id(bot_id.0)
         .framework(framework)
         .event_handler(Handler)
         .await
         .expect("Err creating client");
 
-    let mut info: File = if Path::new("info.json").exists() {
-        OpenOptions::new().read(true).open("info.json").unwrap()
-    } else {
-        {
-            let mut temp = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open("info.json")
-                .unwrap();
-            temp.write(b"{\"title\":\"Sample title\",\"description\": \"Sample description. Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat. Duis aute irure dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat nulla pariatur. Excepteur sint occaecat cupidatat non proident, sunt in culpa qui officia deserunt mollit anim id est laborum.\"}").unwrap();
-        }
-        OpenOptions::new().read(true).open("info.json").unwrap()
+    let play_channels = PlayChannels {
+        guilds: config.play_channels.clone(),
     };
-    let mut json_content: String = String::new();
-    info.read_to_string(&mut json_content).unwrap();
+    if play_channels.guilds.is_empty() {
+        println!("No play channels yet. Use add_play_channel in a channel to let chat play there.");
+    }
 
     {
+        if !Path::new("actions.txt").exists() {
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open("actions.txt")
+                .unwrap();
+            println!(
+                "Create new actions in actions.txt\nSee the GitHub for documentation and examples."
+            );
+        }
+        let actions = match parsing::parse_action_file("actions.txt") {
+            Ok(actions) => actions,
+            Err(errors) => {
+                println!("Could not load actions.txt:");
+                for error in errors {
+                    println!("{}", error);
+                }
+                HashMap::new()
+            }
+        };
         let mut data = client.data.write().await;
         data.insert::<ShardManagerContainer>(client.shard_manager.clone());
-        data.insert::<ActionTracker>(parsing::parse_action_file());
+        let queue = Arc::new(ActionQueue::new(
+            config.queue.max_len,
+            config.queue.drop_policy,
+        ));
+        data.insert::<ExecutorContainer>(executor::spawn(
+            enigo::Enigo::new,
+            actions.clone(),
+            queue,
+        ));
+        data.insert::<ActionTracker>(actions);
         data.insert::<GamerModeTracker>(false);
+        data.insert::<CooldownTracker>(Cooldowns::new(config.cooldown));
+        data.insert::<ChainLimitTracker>(config.chains);
+        data.insert::<MatchingTracker>(config.matching);
+        data.insert::<BudgetTracker>(Budgets::new(
+            config.budget.capacity,
+            config.budget.refill_rate,
+        ));
+        data.insert::<DemocracyTracker>(Democracy {
+            mode: InputMode::Anarchy,
+            automatic: false,
+            pressure: RateMeter::new(PRESSURE_WINDOW),
+            switch: Hysteresis::new(DEFAULT_ANARCHY_BELOW, DEFAULT_DEMOCRACY_ABOVE),
+            window: DEFAULT_VOTE_WINDOW,
+            tally: None,
+        });
         data.insert::<CommandCounter>(HashMap::default());
-        data.insert::<CustomAbout>(serde_json::from_str(&json_content).unwrap())
+        data.insert::<PlayChannelTracker>(play_channels);
+        let mut access = Access::new(
+            config.roles.owners.clone(),
+            config.roles.mods.clone(),
+            config.roles.managers.clone(),
+            config.grants.clone(),
+        );
+        access.bot_owners = bot_owners;
+        data.insert::<PermissionTracker>(access);
+        data.insert::<PlayerTracker>(config.players.clone());
+        data.insert::<ControllerTracker>(Controllers::load(&config.controllers));
+        data.insert::<ReactionControllerTracker>(ReactionControllers {
+            channels: config.reaction_controllers.clone(),
+        });
+        data.insert::<AgeGateTracker>(config.age_gate.clone());
+        data.insert::<CustomAbout>(config.about.clone());
     }
 
     let shard_manager = client.shard_manager.clone();
@@ -689,37 +1603,211 @@ async fn commands(ctx: &Context, msg: &Message) -> CommandResult {
     Ok(())
 }
 
-// A function which acts as a "check", to determine whether to call a command.
-//
-// In this case, this command checks to ensure you are the owner of the message
-// in order for the command to be executed. If the check fails, the command is
-// not called.
+// Someone's roles in a server, and whether they are an administrator there
+async fn roles_of(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    user: UserId,
+    role_ids: &[RoleId],
+) -> (Vec<(u64, String)>, bool) {
+    let mut roles = vec![];
+    let mut administrator = false;
+    if let Some(guild_id) = guild_id {
+        administrator = ctx
+            .cache
+            .guild_field(guild_id, |guild| guild.owner_id)
+            .await
+            == Some(user);
+        for role_id in role_ids {
+            if let Some(role) = role_id.to_role_cached(&ctx.cache).await {
+                administrator |= role.has_permission(Permissions::ADMINISTRATOR);
+                roles.push((role.id.0, role.name));
+            }
+        }
+    }
+    (roles, administrator)
+}
+
+// The author's roles in this server, and whether they are an administrator there
+async fn member_roles(ctx: &Context, msg: &Message) -> (Vec<(u64, String)>, bool) {
+    let role_ids = msg
+        .member
+        .as_ref()
+        .map_or(&[][..], |member| &member.roles[..]);
+    roles_of(ctx, msg.guild_id, msg.author.id, role_ids).await
+}
+
+async fn level_of(ctx: &Context, member: &Member<'_>) -> Level {
+    ctx.data
+        .read()
+        .await
+        .get::<PermissionTracker>()
+        .expect("Expected permissions in TypeMap.")
+        .level(member)
+}
+
+// Works out the author's level from their roles and permissions in this server
+async fn user_level(ctx: &Context, msg: &Message) -> Level {
+    let (roles, administrator) = member_roles(ctx, msg).await;
+    level_of(
+        ctx,
+        &Member {
+            id: msg.author.id.0,
+            roles: &roles,
+            administrator,
+        },
+    )
+    .await
+}
+
+// Blocked players, and players who aren't on the allowlist while it is on, can't send input.
+// Managers and above can always play.
+async fn can_play(ctx: &Context, input: &Input) -> bool {
+    let now = SystemTime::now();
+    let data = ctx.data.read().await;
+    let players = data
+        .get::<PlayerTracker>()
+        .expect("Expected players in TypeMap.");
+    if players.is_blocked(input.user.0, now) {
+        return false;
+    }
+    if !players.allowlist {
+        return true;
+    }
+    drop(data);
+    let (roles, administrator) = input.roles(ctx).await;
+    let member = Member {
+        id: input.user.0,
+        roles: &roles,
+        administrator,
+    };
+    let data = ctx.data.read().await;
+    data.get::<PlayerTracker>()
+        .expect("Expected players in TypeMap.")
+        .can_play(&member, now)
+        || data
+            .get::<PermissionTracker>()
+            .expect("Expected permissions in TypeMap.")
+            .level(&member)
+            >= Level::Manager
+}
+
+// Like "3d 4h" or "12m"
+fn format_wait(wait: Duration) -> String {
+    let minutes = wait.as_secs().div_ceil(60);
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+// New accounts and new members can't play until they are old enough.
+async fn passes_age_gate(ctx: &Context, input: &Input) -> Result<(), Played> {
+    let min_membership = {
+        let data = ctx.data.read().await;
+        let gate = data
+            .get::<AgeGateTracker>()
+            .expect("Expected age gate in TypeMap.");
+        if !gate.is_on() {
+            return Ok(());
+        }
+        gate.min_membership
+    };
+    // Messages and reactions don't always say when someone joined, so ask Discord
+    let mut joined = input.joined;
+    if joined.is_none() && min_membership > Duration::ZERO {
+        if let Some(guild_id) = input.guild_id {
+            joined = guild_id
+                .member(ctx, input.user)
+                .await
+                .ok()
+                .and_then(|member| member.joined_at)
+                .map(|joined| from_unix_millis(joined.timestamp_millis()));
+        }
+    }
+    let too_new = {
+        let data = ctx.data.read().await;
+        let gate = data
+            .get::<AgeGateTracker>()
+            .expect("Expected age gate in TypeMap.");
+        match gate.check(input.user.0, joined, SystemTime::now()) {
+            Ok(()) => return Ok(()),
+            Err(too_new) => too_new,
+        }
+    };
+    let (roles, administrator) = input.roles(ctx).await;
+    let member = Member {
+        id: input.user.0,
+        roles: &roles,
+        administrator,
+    };
+    if level_of(ctx, &member).await >= Level::Manager {
+        return Ok(());
+    }
+    let first_time = ctx
+        .data
+        .write()
+        .await
+        .get_mut::<AgeGateTracker>()
+        .expect("Expected age gate in TypeMap.")
+        .should_tell(input.user.0);
+    let reason = match too_new {
+        TooNew::Account(wait) => format!(
+            "Your Discord account is too new to play here yet. Try again in {}.",
+            format_wait(wait)
+        ),
+        TooNew::Membership(wait) => format!(
+            "You joined this server too recently to play yet. Try again in {}.",
+            format_wait(wait)
+        ),
+    };
+    Err(Played::TooNew { reason, first_time })
+}
+
+// A "check" decides whether a command can be called. These are used by whole groups.
+async fn require_level(ctx: &Context, msg: &Message, level: Level) -> Result<(), Reason> {
+    if user_level(ctx, msg).await >= level {
+        Ok(())
+    } else {
+        Err(Reason::User(format!("Lacked the {} level", level)))
+    }
+}
+
+#[check]
+#[name = "Manager"]
+async fn manager_check(
+    ctx: &Context,
+    msg: &Message,
+    _: &mut Args,
+    _: &CommandOptions,
+) -> Result<(), Reason> {
+    require_level(ctx, msg, Level::Manager).await
+}
+
+#[check]
+#[name = "Mod"]
+async fn mod_check(
+    ctx: &Context,
+    msg: &Message,
+    _: &mut Args,
+    _: &CommandOptions,
+) -> Result<(), Reason> {
+    require_level(ctx, msg, Level::Mod).await
+}
+
 #[check]
 #[name = "Owner"]
 async fn owner_check(
-    _: &Context,
+    ctx: &Context,
     msg: &Message,
     _: &mut Args,
     _: &CommandOptions,
 ) -> Result<(), Reason> {
-    // Replace 7 with your ID to make this check pass.
-    //
-    // 1. If you want to pass a reason alongside failure you can do:
-    // `Reason::User("Lacked admin permission.".to_string())`,
-    //
-    // 2. If you want to mark it as something you want to log only:
-    // `Reason::Log("User lacked admin permission.".to_string())`,
-    //
-    // 3. If the check's failure origin is unknown you can mark it as such:
-    // `Reason::Unknown`
-    //
-    // 4. If you want log for your system and for the user, use:
-    // `Reason::UserAndLog { user, log }`
-    if msg.author.id != 7 {
-        return Err(Reason::User("Lacked owner permission".to_string()));
-    }
-
-    Ok(())
+    require_level(ctx, msg, Level::Owner).await
 }
 
 #[command]
@@ -753,9 +1841,37 @@ async fn about_role(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     Ok(())
 }
 
-#[command]
-async fn about(ctx: &Context, msg: &Message) -> CommandResult {
-    let data = ctx.data.read().await;
+async fn about_embed(ctx: &Context) -> CreateEmbed {
+    let mut data = ctx.data.write().await;
+    let democracy = data
+        .get_mut::<DemocracyTracker>()
+        .expect("Expected democracy tracker in TypeMap.");
+    let now = Instant::now();
+    democracy.refresh(now);
+    let mode = format!(
+        "{}{}",
+        match democracy.mode {
+            InputMode::Anarchy => "Anarchy: every action runs right away",
+            InputMode::Democracy => "Democracy: actions are voted on",
+        },
+        if democracy.automatic {
+            " (picked automatically)"
+        } else {
+            ""
+        }
+    );
+    let pressure = format!(
+        "{:.1} actions per second{}",
+        democracy.pressure.per_second(now),
+        if democracy.automatic {
+            format!(
+                "\nDemocracy above {}, anarchy below {}",
+                democracy.switch.high, democracy.switch.low
+            )
+        } else {
+            "".to_string()
+        }
+    );
     let fields = data
         .get::<CustomAbout>()
         .expect("Expected Actions in TypeMap.");
@@ -765,20 +1881,19 @@ async fn about(ctx: &Context, msg: &Message) -> CommandResult {
         Some(url) => url,
         None => "".to_string(),
     };
-    msg.channel_id
-        .send_message(&ctx.http, |m| {
-            m.embed(|e| {
-                e.title(&fields.title);
-                e.description(&fields.description);
-                e.thumbnail(url);
-                e
-            });
-            m
-        })
-        .await
-        .unwrap();
+    let mut e = CreateEmbed::default();
+    e.title(&fields.title);
+    e.description(&fields.description);
+    e.field("Mode", mode, true);
+    e.field("Chat pressure", pressure, true);
+    e.thumbnail(url);
+    e
+}
 
-    Ok(())
+#[command]
+async fn about(ctx: &Context, msg: &Message) -> CommandResult {
+    let embed = about_embed(ctx).await;
+    send_reply(ctx, msg, Reply::Embed(embed)).await
 }
 
 #[command]
@@ -820,10 +1935,12 @@ async fn latency(ctx: &Context, msg: &Message) -> CommandResult {
 
 #[command]
 async fn slow_mode(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    // `slow_mode_rate` is only renamed to `rate_limit_per_user` in serenity 0.11
+    #[allow(deprecated)]
     let say_content = if let Ok(slow_mode_rate_seconds) = args.single::<u64>() {
         if let Err(why) = msg
             .channel_id
-            .edit(&ctx.http, |c| c.slow_mode_rate(slow_mode_rate_seconds))
+            .edit(&ctx.http, |c| c.rate_limit_per_user(slow_mode_rate_seconds))
             .await
         {
             println!("Error setting channel's slow mode rate: {:?}", why);
@@ -853,8 +1970,29 @@ async fn slow_mode(ctx: &Context, msg: &Message, mut args: Args) -> CommandResul
     Ok(())
 }
 
+// Turns off Discord Plays, cancels every action and lets go of every key.
+// Returns false if the executor didn't confirm the release in time.
+async fn emergency_stop(ctx: &Context) -> bool {
+    let mut data = ctx.data.write().await;
+    let mode = data
+        .get_mut::<GamerModeTracker>()
+        .expect("Expected Game Tracker in TypeMap.");
+    *mode = false;
+    let released = data
+        .get::<ExecutorContainer>()
+        .expect("Expected executor in TypeMap.")
+        .emergency_stop();
+    drop(data);
+    tokio::task::spawn_blocking(move || released.recv_timeout(Duration::from_secs(2)).is_ok())
+        .await
+        .unwrap_or(false)
+}
+
 #[command]
 async fn kill(ctx: &Context, _msg: &Message, _args: Args) -> CommandResult {
+    if !emergency_stop(ctx).await {
+        println!("Could not confirm that every key was released");
+    }
     let data = ctx.data.read().await;
 
     let shard_manager = match data.get::<ShardManagerContainer>() {
@@ -867,151 +2005,1820 @@ async fn kill(ctx: &Context, _msg: &Message, _args: Args) -> CommandResult {
     std::process::exit(0);
 }
 
-#[command]
-async fn reload_actions(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+async fn reload_actions_reply(ctx: &Context) -> Reply {
+    let new_actions = match parsing::parse_action_file("actions.txt") {
+        Ok(new_actions) => new_actions,
+        Err(errors) => {
+            // Keep the old actions running, and tell the mods what to fix
+            let mut list = String::new();
+            for (index, error) in errors.iter().enumerate() {
+                let entry = format!("`{}`\n", error);
+                if list.len() + entry.len() > 4000 {
+                    let _ = writeln!(list, "...and {} more", errors.len() - index);
+                    break;
+                }
+                list += &entry;
+            }
+            let mut e = CreateEmbed::default();
+            e.title(format!(
+                "Could not reload actions ({} error{})",
+                errors.len(),
+                if errors.len() == 1 { "" } else { "s" }
+            ));
+            e.description(list);
+            e.footer(|f| f.text("The previous actions are still loaded."));
+            return Reply::Failed(e);
+        }
+    };
     let mut data = ctx.data.write().await;
+    data.get::<ExecutorContainer>()
+        .expect("Expected executor in TypeMap.")
+        .set_actions(new_actions.clone());
     let actions = data
         .get_mut::<ActionTracker>()
         .expect("Expected Actions in TypeMap.");
-    *actions = parsing::parse_action_file();
-    msg.react(&ctx.http, '✅').await?;
-    Ok(())
+    *actions = new_actions;
+    drop(data);
+    refresh_controllers(ctx).await;
+    refresh_reaction_controllers(ctx).await;
+    Reply::Done
 }
 
 #[command]
-async fn start_discord_plays(ctx: &Context, msg: &Message, mut _args: Args) -> CommandResult {
+async fn reload_actions(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let reply = reload_actions_reply(ctx).await;
+    send_reply(ctx, msg, reply).await
+}
+
+async fn set_playing(ctx: &Context, guild_id: Option<GuildId>, playing: bool) -> Reply {
     let mut data = ctx.data.write().await;
     let mode = data
         .get_mut::<GamerModeTracker>()
         .expect("Expected Game Tracker in TypeMap.");
-    *mode = true;
-    msg.react(&ctx.http, '✅').await?;
-    Ok(())
+    *mode = playing;
+    let has_play_channel = guild_id.is_some_and(|guild_id| {
+        data.get::<PlayChannelTracker>()
+            .expect("Expected play channels in TypeMap.")
+            .guilds
+            .contains_key(&guild_id.0)
+    });
+    if playing && !has_play_channel {
+        return Reply::Text(
+            "Started, but there are no play channels here yet, so nothing will happen. Use `add_play_channel` in a channel to let chat play there.".to_string(),
+        );
+    }
+    Reply::Done
+}
+
+#[command]
+async fn start_discord_plays(ctx: &Context, msg: &Message, mut _args: Args) -> CommandResult {
+    let reply = set_playing(ctx, msg.guild_id, true).await;
+    send_reply(ctx, msg, reply).await
 }
 
 #[command]
 async fn stop_discord_plays(ctx: &Context, msg: &Message, mut _args: Args) -> CommandResult {
-    let mut data = ctx.data.write().await;
-    let mode = data
-        .get_mut::<GamerModeTracker>()
-        .expect("Expected Game Tracker in TypeMap.");
-    *mode = false;
-    msg.react(&ctx.http, '✅').await?;
-    Ok(())
+    let reply = set_playing(ctx, msg.guild_id, false).await;
+    send_reply(ctx, msg, reply).await
 }
 
 #[command]
-async fn set_icon(ctx: &Context, msg: &Message, mut _args: Args) -> CommandResult {
-    //https://docs.rs/serenity/0.9.0/serenity/model/channel/struct.Attachment.html#method.download
-    if msg.attachments.len() > 0 {
-        let content = match msg.attachments[0].download().await {
-            Ok(content) => content,
-            Err(why) => {
-                println!("Error downloading attachment: {:?}", why);
-                let _ = msg.channel_id.say(&ctx, "Error downloading image").await;
+async fn set_mode(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mode = match args.single::<String>().unwrap_or_default().as_str() {
+        "anarchy" => Some(InputMode::Anarchy),
+        "democracy" => Some(InputMode::Democracy),
+        "auto" => None,
+        _ => {
+            msg.reply(
+                &ctx.http,
+                "Usage: `set_mode anarchy`, `set_mode democracy [seconds per vote]` or `set_mode auto [democracy above] [anarchy below]`",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    // Only anarchy and democracy take a time, `auto` takes thresholds
+    let seconds = match mode {
+        Some(_) => args.single::<f64>().ok(),
+        None => None,
+    };
+    let window = match seconds {
+        Some(seconds) => match Duration::try_from_secs_f64(seconds) {
+            Ok(window) if window > Duration::ZERO && window <= MAX_VOTE_WINDOW => Some(window),
+            _ => {
+                msg.reply(
+                    &ctx.http,
+                    format!(
+                        "Votes can last more than 0 and up to {} seconds.",
+                        MAX_VOTE_WINDOW.as_secs()
+                    ),
+                )
+                .await?;
                 return Ok(());
             }
-        };
-        let mut file = match File::create("./avatar.png") {
-            Ok(file) => file,
-            Err(why) => {
-                println!("Error creating file: {:?}", why);
-                let _ = msg.channel_id.say(&ctx, "Error creating file").await;
-                return Ok(());
+        },
+        None => None,
+    };
+    // Thresholds are in actions per second, and left as they are if not given
+    let mut thresholds = [None, None];
+    if mode.is_none() {
+        for threshold in &mut thresholds {
+            let text = match args.single::<String>() {
+                Ok(text) => text,
+                Err(_) => break,
+            };
+            match text.parse::<f64>() {
+                Ok(value) if value.is_finite() && value >= 0.0 => *threshold = Some(value),
+                _ => {
+                    msg.reply(
+                        &ctx.http,
+                        "Usage: `set_mode auto [democracy above] [anarchy below]`, where both are actions per second of 0 or more",
+                    )
+                    .await?;
+                    return Ok(());
+                }
             }
-        };
-
-        if let Err(why) = file.write(&content) {
-            println!("Error writing to file: {:?}", why);
-
-            return Ok(());
         }
-    } else {
-        msg.channel_id
-            .say(
-                &ctx,
-                "No file provided; Please upload an image to change the avatar",
-            )
-            .await?;
-        return Ok(());
     }
+    let mut data = ctx.data.write().await;
+    let democracy = data
+        .get_mut::<DemocracyTracker>()
+        .expect("Expected democracy tracker in TypeMap.");
+    match mode {
+        Some(mode) => {
+            democracy.mode = mode;
+            democracy.automatic = false;
+            if let Some(window) = window {
+                democracy.window = window;
+            }
+        }
+        None => {
+            democracy.automatic = true;
+            let [high, low] = thresholds;
+            let high = high.unwrap_or(democracy.switch.high);
+            let low = low.unwrap_or(democracy.switch.low);
+            democracy.switch.set_thresholds(low, high);
+        }
+    }
+    drop(data);
+    msg.react(&ctx.http, '✅').await?;
+    Ok(())
+}
 
-    let base64 = utils::read_image("./avatar.png").expect("Failed to read image");
+// Sets the bot's avatar to an image, and keeps a copy in avatar.png
+async fn change_icon(ctx: &Context, image: &[u8]) -> Reply {
+    let mut file = match File::create("./avatar.png") {
+        Ok(file) => file,
+        Err(why) => {
+            println!("Error creating file: {:?}", why);
+            return Reply::Text("Error creating file".to_string());
+        }
+    };
+    if let Err(why) = file.write_all(image) {
+        println!("Error writing to file: {:?}", why);
+        return Reply::Text("Error writing file".to_string());
+    }
+    let base64 = match utils::read_image("./avatar.png") {
+        Ok(base64) => base64,
+        Err(why) => {
+            println!("Error reading image: {:?}", why);
+            return Reply::Text("That doesn't look like an image".to_string());
+        }
+    };
 
     let mut user = ctx.cache.current_user().await;
-    let _ = user.edit(&ctx, |p| p.avatar(Some(&base64))).await;
-    msg.channel_id
-        .say(&ctx, "Avatar successfully changed.")
-        .await?;
-    Ok(())
+    if let Err(why) = user.edit(&ctx, |p| p.avatar(Some(&base64))).await {
+        println!("Error changing avatar: {:?}", why);
+        return Reply::Text("Discord didn't accept that image".to_string());
+    }
+    Reply::Text("Avatar successfully changed.".to_string())
 }
 
 #[command]
-async fn set_title(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+async fn set_icon(ctx: &Context, msg: &Message, mut _args: Args) -> CommandResult {
+    //https://docs.rs/serenity/0.9.0/serenity/model/channel/struct.Attachment.html#method.download
+    let attachment = match msg.attachments.first() {
+        Some(attachment) => attachment,
+        None => {
+            msg.channel_id
+                .say(
+                    &ctx,
+                    "No file provided; Please upload an image to change the avatar",
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+    let content = match attachment.download().await {
+        Ok(content) => content,
+        Err(why) => {
+            println!("Error downloading attachment: {:?}", why);
+            let _ = msg.channel_id.say(&ctx, "Error downloading image").await;
+            return Ok(());
+        }
+    };
+    let reply = change_icon(ctx, &content).await;
+    send_reply(ctx, msg, reply).await
+}
+
+// Changes the about text, and saves it in the config file
+async fn set_about(ctx: &Context, title: Option<String>, description: Option<String>) -> Reply {
     let mut data = ctx.data.write().await;
-    let mut about = data
+    let about = data
         .get_mut::<CustomAbout>()
         .expect("Expected CustomAbout in TypeMap.");
-    about.title = args.rest().to_string();
-    let mut info: File = if Path::new("info.json").exists() {
-        OpenOptions::new().write(true).open("info.json").unwrap()
-    } else {
-        OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open("info.json")
-            .unwrap()
-    };
-    info.set_len(0).unwrap();
-    info.write_all(serde_json::to_string(&about).unwrap().as_bytes())
-        .unwrap();
-    msg.react(&ctx.http, '✅').await?;
-    Ok(())
+    if let Some(title) = title {
+        about.title = title;
+    }
+    if let Some(description) = description {
+        about.description = description;
+    }
+    if let Err(why) = config::save_about(CONFIG_PATH, about) {
+        println!("Could not save the about text to {}: {}", CONFIG_PATH, why);
+    }
+    Reply::Done
 }
 
 #[command]
-async fn set_description(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    let mut data = ctx.data.write().await;
-    let mut about = data
-        .get_mut::<CustomAbout>()
-        .expect("Expected CustomAbout in TypeMap.");
-    about.description = args.rest().to_string();
-    let mut info: File = if Path::new("info.json").exists() {
-        OpenOptions::new().write(true).open("info.json").unwrap()
-    } else {
-        OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open("info.json")
-            .unwrap()
-    };
-    info.set_len(0).unwrap();
-    info.write_all(serde_json::to_string(&about).unwrap().as_bytes())
-        .unwrap();
-    msg.react(&ctx.http, '✅').await?;
-    Ok(())
+async fn set_title(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let reply = set_about(ctx, Some(args.rest().to_string()), None).await;
+    send_reply(ctx, msg, reply).await
 }
 
 #[command]
-async fn actions(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+async fn set_description(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let reply = set_about(ctx, None, Some(args.rest().to_string())).await;
+    send_reply(ctx, msg, reply).await
+}
+
+// Who can use an action, like "the mod level" or "Trusted or VIP"
+fn requirement(action: &parsing::Action) -> String {
+    let roles: Vec<String> = action.roles.iter().map(|r| r.to_string()).collect();
+    match (action.level > Level::Player, roles.is_empty()) {
+        (false, true) => "Everyone".to_string(),
+        (true, true) => format!("the {} level", action.level),
+        (false, false) => roles.join(" or "),
+        (true, false) => format!("the {} level and {}", action.level, roles.join(" or ")),
+    }
+}
+
+// Lists every action, or shows the details of one
+async fn actions_reply(ctx: &Context, member: &Member<'_>, name: Option<&str>) -> Reply {
     let data = ctx.data.read().await;
     let actions = data
         .get::<ActionTracker>()
         .expect("Expected ActionTracker in TypeMap.");
+    let access = data
+        .get::<PermissionTracker>()
+        .expect("Expected permissions in TypeMap.");
+    let matching = data
+        .get::<MatchingTracker>()
+        .expect("Expected matching in TypeMap.");
+    let mut e = CreateEmbed::default();
+    if let Some(typed) = name {
+        let name = match chain::find_action(typed, actions, matching) {
+            Some(name) => name,
+            None => {
+                let mut text = format!("There is no action called `{}`.", typed);
+                if let Some(suggestion) = chain::suggest_action(typed, actions, matching) {
+                    let _ = write!(text, " Did you mean `{}`?", suggestion);
+                }
+                return Reply::Text(text);
+            }
+        };
+        let action = &actions[name];
+        e.title(name);
+        e.field(
+            "Cooldown",
+            action
+                .cooldown
+                .map_or("None".to_string(), |c| format!("{:.1}s", c.as_secs_f64())),
+            true,
+        );
+        e.field(
+            "Budget cost",
+            format!("{:.1}s", action.cost.as_secs_f64()),
+            true,
+        );
+        e.field("Who can use it", requirement(action), true);
+        if !action.aliases.is_empty() {
+            e.field("Also called", action.aliases.join(", "), true);
+        }
+        if !access.can_use(member, action.level, &action.roles) {
+            e.footer(|f| f.text("🔒 This action is locked for you"));
+        }
+        return Reply::Embed(e);
+    }
+
+    let mut list: String = String::new();
+    let mut any_locked = false;
+    for (name, action) in actions {
+        list += name;
+        if !action.aliases.is_empty() {
+            let _ = write!(list, " ({})", action.aliases.join(", "));
+        }
+        // Say what is missing, so players know why nothing happens
+        if !access.can_use(member, action.level, &action.roles) {
+            any_locked = true;
+            let needs = if access.level(member) < action.level {
+                format!("the {} level", action.level)
+            } else {
+                let roles: Vec<String> = action.roles.iter().map(|r| r.to_string()).collect();
+                roles.join(" or ")
+            };
+            list += &format!(" 🔒 (needs {})", needs);
+        }
+        list += "\n";
+    }
+    e.title("Current Actions");
+    e.description(list);
+    if any_locked {
+        e.footer(|f| f.text("🔒 actions are locked for you"));
+    }
+    Reply::Embed(e)
+}
+
+#[command]
+async fn actions(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (roles, administrator) = member_roles(ctx, msg).await;
+    let member = Member {
+        id: msg.author.id.0,
+        roles: &roles,
+        administrator,
+    };
+    let name = Some(args.rest().trim()).filter(|name| !name.is_empty());
+    let reply = actions_reply(ctx, &member, name).await;
+    send_reply(ctx, msg, reply).await
+}
+
+#[command]
+async fn queue(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    // Cloned, so the data isn't locked while talking to Discord
+    let executor = ctx
+        .data
+        .read()
+        .await
+        .get::<ExecutorContainer>()
+        .expect("Expected executor in TypeMap.")
+        .clone();
+    let running = executor.queue().running();
+    let waiting = executor.queue().waiting();
     msg.channel_id
         .send_message(&ctx.http, |m| {
             m.embed(|e| {
-                e.title("Current Actions");
+                e.title("Action Queue");
+                e.field(
+                    "Running",
+                    match running {
+                        Some(action) => format!("{} ({})", action.name(), action.requested_by),
+                        None => "Nothing".to_string(),
+                    },
+                    false,
+                );
                 let mut list: String = String::new();
-                for action in actions.keys() {
-                    list += &(action.to_owned() + "\n");
+                for (index, action) in waiting.iter().enumerate() {
+                    list += &format!(
+                        "{}. {} ({})\n",
+                        index + 1,
+                        action.name(),
+                        action.requested_by
+                    );
+                }
+                if list.is_empty() {
+                    list = "The queue is empty.".to_string();
                 }
                 e.description(list);
+                e.footer(|f| {
+                    f.text(format!(
+                        "{}/{} waiting",
+                        waiting.len(),
+                        executor.queue().max_len()
+                    ))
+                });
                 e
             });
             m
         })
+        .await?;
+    Ok(())
+}
+
+#[command]
+async fn skip(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let executor = ctx
+        .data
+        .read()
         .await
-        .unwrap();
+        .get::<ExecutorContainer>()
+        .expect("Expected executor in TypeMap.")
+        .clone();
+    match executor.queue().skip() {
+        Some(action) => {
+            msg.channel_id
+                .say(
+                    &ctx.http,
+                    format!("Skipped {} ({})", action.name(), action.requested_by),
+                )
+                .await?;
+        }
+        None => {
+            msg.channel_id.say(&ctx.http, "The queue is empty.").await?;
+        }
+    }
     Ok(())
 }
+
+#[command]
+async fn clear_queue(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let executor = ctx
+        .data
+        .read()
+        .await
+        .get::<ExecutorContainer>()
+        .expect("Expected executor in TypeMap.")
+        .clone();
+    let removed = executor.queue().clear();
+    msg.channel_id
+        .say(
+            &ctx.http,
+            format!(
+                "Removed {} action{} from the queue.",
+                removed,
+                if removed == 1 { "" } else { "s" }
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("panic")]
+async fn estop(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    if emergency_stop(ctx).await {
+        msg.react(&ctx.http, '🛑').await?;
+    } else {
+        msg.reply(
+            &ctx.http,
+            "Stopped, but the executor did not confirm that every key was released.",
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[command]
+async fn budget(ctx: &Context, msg: &Message) -> CommandResult {
+    let data = ctx.data.read().await;
+    let budgets = data
+        .get::<BudgetTracker>()
+        .expect("Expected budgets in TypeMap.");
+    let remaining = budgets.remaining(msg.author.id.0, Instant::now());
+    let until_full = if budgets.refill_rate > 0.0 {
+        format!(
+            ", full again in {:.1}s",
+            (budgets.capacity - remaining).as_secs_f64() / budgets.refill_rate
+        )
+    } else {
+        "".to_string()
+    };
+    let reply = if remaining >= budgets.capacity {
+        format!(
+            "Your budget is full: {:.1}s of input.",
+            budgets.capacity.as_secs_f64()
+        )
+    } else {
+        format!(
+            "You have {:.1}s of {:.1}s of input left{}.",
+            remaining.as_secs_f64(),
+            budgets.capacity.as_secs_f64(),
+            until_full
+        )
+    };
+    drop(data);
+    msg.reply(&ctx.http, reply).await?;
+    Ok(())
+}
+
+#[command]
+async fn add_play_channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let channel_id = args.single::<ChannelId>().unwrap_or(msg.channel_id);
+    let mut data = ctx.data.write().await;
+    let play_channels = data
+        .get_mut::<PlayChannelTracker>()
+        .expect("Expected play channels in TypeMap.");
+    play_channels
+        .guilds
+        .entry(guild_id.0)
+        .or_default()
+        .insert(channel_id.0);
+    play_channels.save();
+    drop(data);
+    msg.react(&ctx.http, '✅').await?;
+    Ok(())
+}
+
+#[command]
+async fn remove_play_channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = match msg.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let channel_id = args.single::<ChannelId>().unwrap_or(msg.channel_id);
+    let mut data = ctx.data.write().await;
+    let play_channels = data
+        .get_mut::<PlayChannelTracker>()
+        .expect("Expected play channels in TypeMap.");
+    let removed = match play_channels.guilds.get_mut(&guild_id.0) {
+        Some(channels) => channels.remove(&channel_id.0),
+        None => false,
+    };
+    if !removed {
+        drop(data);
+        msg.reply(&ctx.http, "That isn't a play channel.").await?;
+        return Ok(());
+    }
+    play_channels
+        .guilds
+        .retain(|_, channels| !channels.is_empty());
+    play_channels.save();
+    drop(data);
+    msg.react(&ctx.http, '✅').await?;
+    Ok(())
+}
+
+// Reads a user or role mention, or a plain ID
+fn parse_target(target: &str) -> Option<u64> {
+    utils::parse_username(target)
+        .or_else(|| utils::parse_role(target))
+        .or_else(|| target.parse().ok())
+}
+
+fn save_grants(access: &Access) {
+    if let Err(why) = config::save_grants(CONFIG_PATH, access.grants()) {
+        println!("Could not save grants to {}: {}", CONFIG_PATH, why);
+    }
+}
+
+#[command]
+async fn grant(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let target = args.single::<String>().ok().and_then(|t| parse_target(&t));
+    let level = args
+        .single::<String>()
+        .ok()
+        .and_then(|l| l.parse::<Level>().ok());
+    let (target, level) = match (target, level) {
+        (Some(target), Some(level)) => (target, level),
+        _ => {
+            msg.reply(
+                &ctx.http,
+                format!(
+                    "Usage: `grant @user|@role level`, where level is one of {}",
+                    LEVEL_NAMES.join(", ")
+                ),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let mut data = ctx.data.write().await;
+    let access = data
+        .get_mut::<PermissionTracker>()
+        .expect("Expected permissions in TypeMap.");
+    access.grant(target, level);
+    save_grants(access);
+    drop(data);
+    msg.react(&ctx.http, '✅').await?;
+    Ok(())
+}
+
+#[command]
+async fn revoke(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let target = match args.single::<String>().ok().and_then(|t| parse_target(&t)) {
+        Some(target) => target,
+        None => {
+            msg.reply(&ctx.http, "Usage: `revoke @user|@role`").await?;
+            return Ok(());
+        }
+    };
+    let mut data = ctx.data.write().await;
+    let access = data
+        .get_mut::<PermissionTracker>()
+        .expect("Expected permissions in TypeMap.");
+    let revoked = access.revoke(target);
+    if revoked.is_some() {
+        save_grants(access);
+    }
+    drop(data);
+    match revoked {
+        Some(level) => {
+            msg.reply(&ctx.http, format!("Took away the {} level.", level))
+                .await?;
+        }
+        None => {
+            msg.reply(
+                &ctx.http,
+                "They weren't granted a level. Levels from roles are set in config.toml.",
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+fn save_players(players: &mut PlayerFilter) {
+    players.remove_expired(SystemTime::now());
+    if let Err(why) = config::save_players(CONFIG_PATH, players) {
+        println!("Could not save players to {}: {}", CONFIG_PATH, why);
+    }
+}
+
+#[command]
+async fn block_player(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let user = match args
+        .single::<String>()
+        .ok()
+        .and_then(|u| utils::parse_username(&u).or_else(|| u.parse().ok()))
+    {
+        Some(user) => user,
+        None => {
+            msg.reply(&ctx.http, "Usage: `block_player @someone [time]`")
+                .await?;
+            return Ok(());
+        }
+    };
+    let duration = match args.single::<String>() {
+        Ok(time) => match parsing::parse_duration(&time) {
+            Some(duration) => Some(duration),
+            None => {
+                msg.reply(&ctx.http, "Times look like `30m`, `2h` or `7d`, up to a year.")
+                    .await?;
+                return Ok(());
+            }
+        },
+        Err(_) => None,
+    };
+    let mut data = ctx.data.write().await;
+    let players = data
+        .get_mut::<PlayerTracker>()
+        .expect("Expected players in TypeMap.");
+    players.block(user, duration.map(|duration| SystemTime::now() + duration));
+    save_players(players);
+    drop(data);
+    msg.react(&ctx.http, '✅').await?;
+    Ok(())
+}
+
+#[command]
+async fn unblock_player(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let user = match args
+        .single::<String>()
+        .ok()
+        .and_then(|u| utils::parse_username(&u).or_else(|| u.parse().ok()))
+    {
+        Some(user) => user,
+        None => {
+            msg.reply(&ctx.http, "Usage: `unblock_player @someone`")
+                .await?;
+            return Ok(());
+        }
+    };
+    let mut data = ctx.data.write().await;
+    let players = data
+        .get_mut::<PlayerTracker>()
+        .expect("Expected players in TypeMap.");
+    let now = SystemTime::now();
+    let was_blocked = players.is_blocked(user, now);
+    players.unblock(user);
+    save_players(players);
+    drop(data);
+    if was_blocked {
+        msg.react(&ctx.http, '✅').await?;
+    } else {
+        msg.reply(&ctx.http, "They aren't blocked.").await?;
+    }
+    Ok(())
+}
+
+#[command]
+async fn allowlist(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let subcommand = args.single::<String>().unwrap_or_default();
+    // Mentions become IDs, anything else is a role name
+    let target = match args.rest().trim() {
+        "" => None,
+        rest => Some(
+            utils::parse_username(rest)
+                .or_else(|| utils::parse_role(rest))
+                .map_or_else(|| RoleRef::new(rest), RoleRef::Id),
+        ),
+    };
+    let mut data = ctx.data.write().await;
+    let players = data
+        .get_mut::<PlayerTracker>()
+        .expect("Expected players in TypeMap.");
+    let reply = match (subcommand.as_str(), target) {
+        ("on", None) => {
+            players.allowlist = true;
+            "The allowlist is on. Only the users and roles on it can play.".to_string()
+        }
+        ("off", None) => {
+            players.allowlist = false;
+            "The allowlist is off. Everyone can play.".to_string()
+        }
+        ("add", Some(target)) => {
+            // IDs are shown as they are, since they might be users or roles
+            let reply = format!("Added {} to the allowlist.", target.config_name());
+            players.allow(target);
+            reply
+        }
+        ("remove", Some(target)) => {
+            if players.disallow(&target) {
+                format!("Removed {} from the allowlist.", target.config_name())
+            } else {
+                format!("{} isn't on the allowlist.", target.config_name())
+            }
+        }
+        ("", None) => {
+            let allowed: Vec<String> = players.allowed.iter().map(|a| a.config_name()).collect();
+            let reply = format!(
+                "The allowlist is {}. On it: {}",
+                if players.allowlist { "on" } else { "off" },
+                if allowed.is_empty() {
+                    "nobody".to_string()
+                } else {
+                    allowed.join(", ")
+                }
+            );
+            drop(data);
+            msg.channel_id.say(&ctx.http, reply).await?;
+            return Ok(());
+        }
+        _ => {
+            drop(data);
+            msg.reply(
+                &ctx.http,
+                "Usage: `allowlist`, `allowlist on`, `allowlist off`, `allowlist add @someone` or `allowlist remove @someone`",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    save_players(players);
+    drop(data);
+    msg.channel_id.say(&ctx.http, reply).await?;
+    Ok(())
+}
+
+// What a command says back. The same command can answer a prefix command or a slash command.
+enum Reply {
+    Done,
+    Text(String),
+    Embed(CreateEmbed),
+    // An embed saying what went wrong
+    Failed(CreateEmbed),
+}
+
+async fn send_reply(ctx: &Context, msg: &Message, reply: Reply) -> CommandResult {
+    match reply {
+        Reply::Done => {
+            msg.react(&ctx.http, '✅').await?;
+        }
+        Reply::Text(text) => {
+            msg.reply(&ctx.http, text).await?;
+        }
+        Reply::Embed(embed) => {
+            msg.channel_id
+                .send_message(&ctx.http, |m| m.set_embed(embed))
+                .await?;
+        }
+        Reply::Failed(embed) => {
+            msg.channel_id
+                .send_message(&ctx.http, |m| m.set_embed(embed))
+                .await?;
+            msg.react(&ctx.http, '❌').await?;
+        }
+    }
+    Ok(())
+}
+
+// Only the person who used the command sees short answers
+async fn respond(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    reply: Reply,
+) -> serenity::Result<()> {
+    command
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| match reply {
+                    Reply::Done => d
+                        .content("✅")
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL),
+                    Reply::Text(text) => d
+                        .content(text)
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL),
+                    Reply::Embed(embed) | Reply::Failed(embed) => d.add_embed(embed),
+                })
+        })
+        .await
+}
+
+fn register_slash_commands(
+    commands: &mut CreateApplicationCommands,
+) -> &mut CreateApplicationCommands {
+    commands
+        .create_application_command(|c| c.name("about").description("About this bot"))
+        .create_application_command(|c| {
+            c.name("actions")
+                .description("Lists the actions, or shows one of them")
+                .create_option(|o| {
+                    o.name("action")
+                        .description("The action to show")
+                        .kind(ApplicationCommandOptionType::String)
+                        .set_autocomplete(true)
+                })
+        })
+        .create_application_command(|c| {
+            c.name("start_discord_plays")
+                .description("Lets chat start playing")
+        })
+        .create_application_command(|c| {
+            c.name("stop_discord_plays")
+                .description("Stops chat from playing")
+        })
+        .create_application_command(|c| {
+            c.name("reload_actions")
+                .description("Loads actions.txt again")
+        })
+        .create_application_command(|c| {
+            c.name("set_title")
+                .description("Changes the title of the about page")
+                .create_option(|o| {
+                    o.name("title")
+                        .description("The new title")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_application_command(|c| {
+            c.name("set_description")
+                .description("Changes the description on the about page")
+                .create_option(|o| {
+                    o.name("description")
+                        .description("The new description")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_application_command(|c| {
+            c.name("set_icon")
+                .description("Changes the bot's avatar")
+                .create_option(|o| {
+                    o.name("url")
+                        .description("A link to the new avatar image")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                })
+        })
+}
+
+// Discord doesn't take avatars bigger than this
+const MAX_AVATAR_SIZE: usize = 10 * 1024 * 1024;
+// How long a link gets to send the whole image
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+enum DownloadError {
+    NotHttps,
+    TooBig,
+    Request(reqwest::Error),
+}
+
+impl From<reqwest::Error> for DownloadError {
+    fn from(why: reqwest::Error) -> DownloadError {
+        DownloadError::Request(why)
+    }
+}
+
+async fn download(url: &str) -> Result<Vec<u8>, DownloadError> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "https" => {}
+        _ => return Err(DownloadError::NotHttps),
+    }
+    // https_only also stops redirects to plain http
+    let client = reqwest::Client::builder()
+        .https_only(true)
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()?;
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_AVATAR_SIZE as u64)
+    {
+        return Err(DownloadError::TooBig);
+    }
+    // The length can be missing or wrong, so stop reading once there is too much
+    let mut image = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        if image.len() + chunk.len() > MAX_AVATAR_SIZE {
+            return Err(DownloadError::TooBig);
+        }
+        image.extend_from_slice(&chunk);
+    }
+    Ok(image)
+}
+
+async fn run_slash_command(ctx: &Context, command: &ApplicationCommandInteraction) {
+    let (roles, administrator) = match &command.member {
+        Some(member) => roles_of(ctx, command.guild_id, member.user.id, &member.roles).await,
+        None => (vec![], false),
+    };
+    let member = Member {
+        id: command.user.id.0,
+        roles: &roles,
+        administrator,
+    };
+    let level = level_of(ctx, &member).await;
+    let option = |name: &str| {
+        command
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == name)
+            .and_then(|option| option.value.as_ref())
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_string())
+    };
+
+    let reply = match command.data.name.as_str() {
+        "about" => Reply::Embed(about_embed(ctx).await),
+        "actions" => actions_reply(ctx, &member, option("action").as_deref()).await,
+        // Everything below is for managers, like the prefix versions
+        name if level < Level::Manager => {
+            Reply::Text(format!("Only managers and above can use /{}.", name))
+        }
+        "start_discord_plays" => set_playing(ctx, command.guild_id, true).await,
+        "stop_discord_plays" => set_playing(ctx, command.guild_id, false).await,
+        "reload_actions" => reload_actions_reply(ctx).await,
+        "set_title" => set_about(ctx, option("title"), None).await,
+        "set_description" => set_about(ctx, None, option("description")).await,
+        "set_icon" => {
+            // Downloading and uploading can take longer than Discord waits for an answer
+            if let Err(why) = command.defer(&ctx.http).await {
+                println!("Could not answer /set_icon: {:?}", why);
+                return;
+            }
+            let url = option("url").unwrap_or_default();
+            let reply = match download(&url).await {
+                Ok(image) => change_icon(ctx, &image).await,
+                Err(DownloadError::NotHttps) => {
+                    Reply::Text("The link has to start with https://".to_string())
+                }
+                Err(DownloadError::TooBig) => Reply::Text(format!(
+                    "That image is too big, avatars can be up to {} MB",
+                    MAX_AVATAR_SIZE / 1024 / 1024
+                )),
+                Err(DownloadError::Request(why)) => {
+                    println!("Error downloading {}: {:?}", url, why);
+                    Reply::Text("Error downloading image".to_string())
+                }
+            };
+            let text = match reply {
+                Reply::Text(text) => text,
+                _ => "✅".to_string(),
+            };
+            if let Err(why) = command
+                .edit_original_interaction_response(&ctx.http, |r| r.content(text))
+                .await
+            {
+                println!("Could not answer /set_icon: {:?}", why);
+            }
+            return;
+        }
+        _ => return,
+    };
+    if let Err(why) = respond(ctx, command, reply).await {
+        println!("Could not answer /{}: {:?}", command.data.name, why);
+    }
+}
+
+// Suggests action names for the `action` option, matching what has been typed so far
+async fn autocomplete_action(ctx: &Context, autocomplete: &AutocompleteInteraction) {
+    let typed = autocomplete
+        .data
+        .options
+        .iter()
+        .find(|option| option.focused)
+        .and_then(|option| option.value.as_ref())
+        .and_then(|value| value.as_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let mut names: Vec<String> = ctx
+        .data
+        .read()
+        .await
+        .get::<ActionTracker>()
+        .expect("Expected ActionTracker in TypeMap.")
+        .iter()
+        .filter(|(name, action)| {
+            action.pattern.is_none() && name.to_lowercase().contains(&typed)
+        })
+        .map(|(name, _)| name.clone())
+        .collect();
+    names.sort();
+    // Discord shows at most 25 choices
+    names.truncate(25);
+    let result = autocomplete
+        .create_autocomplete_response(&ctx.http, |r| {
+            for name in &names {
+                r.add_string_choice(name, name);
+            }
+            r
+        })
+        .await;
+    if let Err(why) = result {
+        println!("Could not suggest actions: {:?}", why);
+    }
+}
+
+// Button IDs start with these, followed by the action name or page number
+const PLAY_BUTTON: &str = "play:";
+const PAGE_BUTTON: &str = "controller_page:";
+// Discord's limits
+const BUTTONS_PER_ROW: usize = 5;
+const ROWS_PER_MESSAGE: usize = 5;
+const MAX_BUTTON_ID_LEN: usize = 100;
+const MAX_BUTTON_LABEL_LEN: usize = 80;
+
+// The actions that get a button, sorted, and how many have names too long for one.
+// Pattern actions need something typed to match, so they don't get one.
+async fn controller_actions(ctx: &Context) -> (Vec<String>, usize) {
+    let data = ctx.data.read().await;
+    let (mut names, too_long): (Vec<String>, Vec<String>) = data
+        .get::<ActionTracker>()
+        .expect("Expected ActionTracker in TypeMap.")
+        .iter()
+        .filter(|(_, action)| action.pattern.is_none())
+        .map(|(name, _)| name.clone())
+        .partition(|name| PLAY_BUTTON.len() + name.len() <= MAX_BUTTON_ID_LEN);
+    names.sort();
+    (names, too_long.len())
+}
+
+// Everything fits on one page if it can, otherwise the last row is for changing pages
+fn buttons_per_page(actions: usize) -> usize {
+    if actions <= BUTTONS_PER_ROW * ROWS_PER_MESSAGE {
+        BUTTONS_PER_ROW * ROWS_PER_MESSAGE
+    } else {
+        BUTTONS_PER_ROW * (ROWS_PER_MESSAGE - 1)
+    }
+}
+
+fn controller_pages(actions: usize) -> usize {
+    actions.div_ceil(buttons_per_page(actions)).max(1)
+}
+
+fn controller_text(names: &[String], too_long: usize) -> String {
+    let mut text = if names.is_empty() {
+        "There are no actions to press yet.".to_string()
+    } else {
+        "Press a button to play.".to_string()
+    };
+    if too_long > 0 {
+        let _ = write!(
+            text,
+            "\n{} action{} too long for a button.",
+            too_long,
+            if too_long == 1 {
+                " has a name"
+            } else {
+                "s have names"
+            }
+        );
+    }
+    text
+}
+
+fn controller_components<'a>(
+    components: &'a mut CreateComponents,
+    names: &[String],
+    page: usize,
+) -> &'a mut CreateComponents {
+    let per_page = buttons_per_page(names.len());
+    let pages = controller_pages(names.len());
+    // Actions can have been removed since the page was picked
+    let page = page.min(pages - 1);
+    let on_page = names.chunks(per_page).nth(page).unwrap_or_default();
+    for row in on_page.chunks(BUTTONS_PER_ROW) {
+        components.create_action_row(|r| {
+            for name in row {
+                r.create_button(|b| {
+                    b.style(ButtonStyle::Primary)
+                        .label(name.chars().take(MAX_BUTTON_LABEL_LEN).collect::<String>())
+                        .custom_id(format!("{}{}", PLAY_BUTTON, name))
+                });
+            }
+            r
+        });
+    }
+    if pages > 1 {
+        components.create_action_row(|r| {
+            r.create_button(|b| {
+                b.style(ButtonStyle::Secondary)
+                    .label("◀")
+                    .custom_id(format!("{}{}", PAGE_BUTTON, page.saturating_sub(1)))
+                    .disabled(page == 0)
+            })
+            .create_button(|b| {
+                b.style(ButtonStyle::Secondary)
+                    .label(format!("Page {}/{}", page + 1, pages))
+                    .custom_id("controller_page_number")
+                    .disabled(true)
+            })
+            .create_button(|b| {
+                b.style(ButtonStyle::Secondary)
+                    .label("▶")
+                    .custom_id(format!("{}{}", PAGE_BUTTON, page + 1))
+                    .disabled(page + 1 >= pages)
+            })
+        });
+    }
+    components
+}
+
+async fn in_play_channel(ctx: &Context, msg: &Message) -> bool {
+    match msg.guild_id {
+        Some(guild_id) => ctx
+            .data
+            .read()
+            .await
+            .get::<PlayChannelTracker>()
+            .expect("Expected play channels in TypeMap.")
+            .contains(guild_id, msg.channel_id),
+        None => false,
+    }
+}
+
+#[command]
+async fn controller(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    if !in_play_channel(ctx, msg).await {
+        msg.reply(
+            &ctx.http,
+            "The buttons only work in a play channel. Use `add_play_channel` here first.",
+        )
+        .await?;
+        return Ok(());
+    }
+    let (names, too_long) = controller_actions(ctx).await;
+    let panel = msg
+        .channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(controller_text(&names, too_long))
+                .components(|c| controller_components(c, &names, 0))
+        })
+        .await?;
+    let mut data = ctx.data.write().await;
+    let controllers = data
+        .get_mut::<ControllerTracker>()
+        .expect("Expected controllers in TypeMap.");
+    controllers.panels.insert(panel.id, (msg.channel_id, 0));
+    controllers.save();
+    Ok(())
+}
+
+// Redraws every controller with the current actions, and forgets the ones that were deleted
+async fn refresh_controllers(ctx: &Context) {
+    let panels: Vec<(MessageId, (ChannelId, usize))> = ctx
+        .data
+        .read()
+        .await
+        .get::<ControllerTracker>()
+        .expect("Expected controllers in TypeMap.")
+        .panels
+        .iter()
+        .map(|(id, panel)| (*id, *panel))
+        .collect();
+    if panels.is_empty() {
+        return;
+    }
+    let (names, too_long) = controller_actions(ctx).await;
+    let last_page = controller_pages(names.len()) - 1;
+    for (id, (channel_id, page)) in panels {
+        let page = page.min(last_page);
+        let edited = channel_id
+            .edit_message(&ctx.http, id, |m| {
+                m.content(controller_text(&names, too_long))
+                    .components(|c| controller_components(c, &names, page))
+            })
+            .await;
+        let mut data = ctx.data.write().await;
+        let controllers = data
+            .get_mut::<ControllerTracker>()
+            .expect("Expected controllers in TypeMap.");
+        match edited {
+            Ok(_) => {
+                controllers.panels.insert(id, (channel_id, page));
+            }
+            Err(why) if is_not_found(&why) => {
+                controllers.panels.remove(&id);
+                controllers.save();
+            }
+            Err(why) => println!("Could not refresh the controller {}: {:?}", id, why),
+        }
+    }
+}
+
+// A button press on a controller: either an action, or a different page
+async fn press_button(ctx: &Context, component: &MessageComponentInteraction) {
+    let custom_id = &component.data.custom_id;
+    let result = if let Some(page) = custom_id.strip_prefix(PAGE_BUTTON) {
+        let (names, too_long) = controller_actions(ctx).await;
+        let page = page
+            .parse::<usize>()
+            .unwrap_or_default()
+            .min(controller_pages(names.len()) - 1);
+        // Panels missing from the config file, like ones from older versions, are tracked again
+        {
+            let mut data = ctx.data.write().await;
+            let controllers = data
+                .get_mut::<ControllerTracker>()
+                .expect("Expected controllers in TypeMap.");
+            let panel = (component.channel_id, page);
+            if controllers
+                .panels
+                .insert(component.message.id, panel)
+                .is_none()
+            {
+                controllers.save();
+            }
+        }
+        component
+            .create_interaction_response(&ctx.http, |r| {
+                r.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|d| {
+                        d.content(controller_text(&names, too_long))
+                            .components(|c| controller_components(c, &names, page))
+                    })
+            })
+            .await
+    } else if let Some(name) = custom_id.strip_prefix(PLAY_BUTTON) {
+        let input = Input::from_member(
+            component.guild_id,
+            component.channel_id,
+            &component.user,
+            component.member.as_ref(),
+        );
+        let problem = match play(ctx, &input, name).await {
+            Played::Sent => None,
+            Played::Ignored => Some("Discord Plays isn't running here right now.".to_string()),
+            Played::NotAllowed => Some("You can't play right now.".to_string()),
+            Played::TooNew { reason, .. } => Some(reason),
+            Played::Locked => Some("🔒 You can't use that action.".to_string()),
+            Played::OutOfBudget => {
+                Some("🪫 You are out of input time. It fills back up slowly.".to_string())
+            }
+            Played::OnCooldown => Some("⏱ That action is on cooldown.".to_string()),
+            Played::QueueFull => Some("🚫 The queue is full.".to_string()),
+            Played::TooMuch(why) => Some(format!("📏 {}.", why)),
+            Played::Misspelled(name) => Some(format!("Did you mean {}?", name)),
+        };
+        match problem {
+            // Pressing the button is enough of an answer
+            None => {
+                component
+                    .create_interaction_response(&ctx.http, |r| {
+                        r.kind(InteractionResponseType::DeferredUpdateMessage)
+                    })
+                    .await
+            }
+            // Only the person who pressed it sees why nothing happened
+            Some(problem) => {
+                component
+                    .create_interaction_response(&ctx.http, |r| {
+                        r.kind(InteractionResponseType::ChannelMessageWithSource)
+                            .interaction_response_data(|d| {
+                                d.content(problem).flags(
+                                    InteractionApplicationCommandCallbackDataFlags::EPHEMERAL,
+                                )
+                            })
+                    })
+                    .await
+            }
+        }
+    } else {
+        return;
+    };
+    if let Err(why) = result {
+        println!("Could not answer a button press: {:?}", why);
+    }
+}
+
+// Discord only lets a message have this many different reactions
+const MAX_REACTIONS: usize = 20;
+
+// The actions that have an emoji, sorted by name
+async fn reaction_actions(ctx: &Context) -> Vec<(ReactionType, String)> {
+    let data = ctx.data.read().await;
+    let mut pairs: Vec<(ReactionType, String)> = data
+        .get::<ActionTracker>()
+        .expect("Expected ActionTracker in TypeMap.")
+        .iter()
+        .filter_map(|(name, action)| {
+            let emoji = action.emoji.as_deref()?.parse::<ReactionType>().ok()?;
+            Some((emoji, name.clone()))
+        })
+        .collect();
+    pairs.sort_by(|(_, a), (_, b)| a.cmp(b));
+    pairs
+}
+
+// '⬆' and '⬆️' are the same emoji, and custom emoji can be renamed
+fn same_emoji(a: &ReactionType, b: &ReactionType) -> bool {
+    match (a, b) {
+        (ReactionType::Unicode(a), ReactionType::Unicode(b)) => {
+            a.replace('\u{fe0f}', "") == b.replace('\u{fe0f}', "")
+        }
+        (ReactionType::Custom { id: a, .. }, ReactionType::Custom { id: b, .. }) => a == b,
+        _ => false,
+    }
+}
+
+fn reaction_controller_text(pairs: &[(ReactionType, String)]) -> String {
+    if pairs.is_empty() {
+        return "No actions have an emoji right now.".to_string();
+    }
+    let mut text = "React to play:".to_string();
+    for (emoji, name) in pairs.iter().take(MAX_REACTIONS) {
+        let _ = write!(text, "\n{} {}", emoji, name);
+    }
+    if pairs.len() > MAX_REACTIONS {
+        let _ = write!(
+            text,
+            "\n(Only {} reactions fit on a message, so {} more can't be used here.)",
+            MAX_REACTIONS,
+            pairs.len() - MAX_REACTIONS
+        );
+    }
+    text
+}
+
+// Gives a reaction controller one reaction from the bot for each action, and takes
+// back the ones for actions that are gone
+async fn sync_reactions(ctx: &Context, message: &Message, pairs: &[(ReactionType, String)]) {
+    let ours: Vec<ReactionType> = message
+        .reactions
+        .iter()
+        .filter(|reaction| reaction.me)
+        .map(|reaction| reaction.reaction_type.clone())
+        .collect();
+    let (remove, add) = reaction_changes(&ours, pairs);
+    for emoji in remove {
+        let _ = message
+            .channel_id
+            .delete_reaction(&ctx.http, message.id, None, emoji)
+            .await;
+    }
+    for (emoji, name) in add {
+        // Custom emoji only work if the bot is in the server they are from
+        if let Err(why) = message.react(&ctx.http, emoji.clone()).await {
+            println!("Could not add the emoji for '{}': {:?}", name, why);
+        }
+    }
+}
+
+// Which of the bot's reactions to take away, and which actions still need one
+fn reaction_changes<'a>(
+    ours: &[ReactionType],
+    pairs: &'a [(ReactionType, String)],
+) -> (Vec<ReactionType>, Vec<&'a (ReactionType, String)>) {
+    let wanted = &pairs[..pairs.len().min(MAX_REACTIONS)];
+    let remove = ours
+        .iter()
+        .filter(|reaction| !wanted.iter().any(|(emoji, _)| same_emoji(emoji, reaction)))
+        .cloned()
+        .collect();
+    let add = wanted
+        .iter()
+        .filter(|(emoji, _)| !ours.iter().any(|reaction| same_emoji(emoji, reaction)))
+        .collect();
+    (remove, add)
+}
+
+#[command]
+async fn reaction_controller(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    if !in_play_channel(ctx, msg).await {
+        msg.reply(
+            &ctx.http,
+            "Reactions only work in a play channel. Use `add_play_channel` here first.",
+        )
+        .await?;
+        return Ok(());
+    }
+    let pairs = reaction_actions(ctx).await;
+    if pairs.is_empty() {
+        msg.reply(
+            &ctx.http,
+            "No actions have an emoji yet. Give them one in actions.txt, like `Up [emoji=⬆️]:`.",
+        )
+        .await?;
+        return Ok(());
+    }
+    let panel = msg
+        .channel_id
+        .say(&ctx.http, reaction_controller_text(&pairs))
+        .await?;
+    {
+        let mut data = ctx.data.write().await;
+        let controllers = data
+            .get_mut::<ReactionControllerTracker>()
+            .expect("Expected reaction controllers in TypeMap.");
+        controllers
+            .channels
+            .entry(msg.channel_id.0)
+            .or_default()
+            .insert(panel.id.0);
+        controllers.save();
+    }
+    sync_reactions(ctx, &panel, &pairs).await;
+    Ok(())
+}
+
+fn is_not_found(why: &serenity::Error) -> bool {
+    match why {
+        serenity::Error::Http(why) => match why.as_ref() {
+            HttpError::UnsuccessfulRequest(response) => response.status_code.as_u16() == 404,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// Redraws every reaction controller with the current actions, and forgets the ones that were deleted
+async fn refresh_reaction_controllers(ctx: &Context) {
+    let panels: Vec<(ChannelId, MessageId)> = ctx
+        .data
+        .read()
+        .await
+        .get::<ReactionControllerTracker>()
+        .expect("Expected reaction controllers in TypeMap.")
+        .channels
+        .iter()
+        .flat_map(|(channel, messages)| {
+            messages
+                .iter()
+                .map(move |message| (ChannelId(*channel), MessageId(*message)))
+        })
+        .collect();
+    if panels.is_empty() {
+        return;
+    }
+    let pairs = reaction_actions(ctx).await;
+    for (channel_id, message_id) in panels {
+        let edited = channel_id
+            .edit_message(&ctx.http, message_id, |m| {
+                m.content(reaction_controller_text(&pairs))
+            })
+            .await;
+        match edited {
+            Ok(message) => sync_reactions(ctx, &message, &pairs).await,
+            Err(why) if is_not_found(&why) => {
+                let mut data = ctx.data.write().await;
+                let controllers = data
+                    .get_mut::<ReactionControllerTracker>()
+                    .expect("Expected reaction controllers in TypeMap.");
+                controllers.remove(channel_id, message_id);
+                controllers.save();
+            }
+            Err(why) => println!(
+                "Could not refresh the reaction controller {}: {:?}",
+                message_id, why
+            ),
+        }
+    }
+}
+
+// A reaction on a reaction controller sends its action, then gets taken away so it can be used again
+async fn press_reaction(ctx: &Context, reaction: &Reaction) {
+    let user_id = match reaction.user_id {
+        Some(user_id) => user_id,
+        None => return,
+    };
+    if user_id == ctx.cache.current_user_id().await {
+        return;
+    }
+    let is_controller = ctx
+        .data
+        .read()
+        .await
+        .get::<ReactionControllerTracker>()
+        .expect("Expected reaction controllers in TypeMap.")
+        .contains(reaction.channel_id, reaction.message_id);
+    if !is_controller {
+        return;
+    }
+    let name = reaction_actions(ctx)
+        .await
+        .into_iter()
+        .find(|(emoji, _)| same_emoji(emoji, &reaction.emoji))
+        .map(|(_, name)| name);
+    if let Some(name) = name {
+        match reaction.user(ctx).await {
+            Ok(user) => {
+                let input = Input::from_reaction(reaction, &user);
+                // There is nowhere private to say why a reaction didn't work,
+                // so only players who are too new get told, once
+                if let Played::TooNew {
+                    reason,
+                    first_time: true,
+                } = play(ctx, &input, &name).await
+                {
+                    let _ = reaction
+                        .channel_id
+                        .say(&ctx.http, format!("{} {}", user.mention(), reason))
+                        .await;
+                }
+            }
+            Err(why) => println!("Could not look up who reacted: {:?}", why),
+        }
+    }
+    if let Err(why) = reaction.delete(ctx).await {
+        println!("Could not remove a reaction from a controller: {:?}", why);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn names(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|index| format!("action{:02}", index))
+            .collect()
+    }
+
+    // Each row of buttons, as (custom ID, label, disabled)
+    fn rows(names: &[String], page: usize) -> Vec<Vec<(String, String, bool)>> {
+        let mut components = CreateComponents::default();
+        controller_components(&mut components, names, page);
+        components
+            .0
+            .iter()
+            .map(|row| {
+                row["components"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|button| {
+                        (
+                            button["custom_id"].as_str().unwrap().to_string(),
+                            button["label"].as_str().unwrap().to_string(),
+                            button.get("disabled").and_then(Value::as_bool) == Some(true),
+                        )
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pages_only_start_past_25_actions() {
+        assert_eq!(buttons_per_page(25), 25);
+        assert_eq!(controller_pages(25), 1);
+        assert_eq!(controller_pages(0), 1);
+        // The last row becomes the page buttons
+        assert_eq!(buttons_per_page(26), 20);
+        assert_eq!(controller_pages(26), 2);
+        assert_eq!(controller_pages(41), 3);
+
+        let rows_25 = rows(&names(25), 0);
+        assert_eq!(rows_25.len(), 5);
+        assert!(rows_25
+            .iter()
+            .flatten()
+            .all(|(id, _, _)| id.starts_with(PLAY_BUTTON)));
+
+        let rows_26 = rows(&names(26), 0);
+        assert_eq!(rows_26.len(), 5);
+        assert_eq!(rows_26[..4].iter().flatten().count(), 20);
+        assert_eq!(rows_26[4][1].1, "Page 1/2");
+    }
+
+    #[test]
+    fn page_buttons_stop_at_the_ends() {
+        let first = rows(&names(26), 0);
+        let navigation = &first[4];
+        assert_eq!((navigation[0].1.as_str(), navigation[0].2), ("◀", true));
+        assert_eq!((navigation[2].1.as_str(), navigation[2].2), ("▶", false));
+        assert_eq!(navigation[2].0, format!("{}1", PAGE_BUTTON));
+
+        let last = rows(&names(26), 1);
+        assert_eq!(last[0].len(), 5);
+        assert_eq!(last[1].len(), 1);
+        let navigation = &last[2];
+        assert_eq!((navigation[0].2, navigation[2].2), (false, true));
+        assert_eq!(navigation[1].1, "Page 2/2");
+    }
+
+    #[test]
+    fn pages_past_the_end_show_the_last_page() {
+        assert_eq!(rows(&names(26), 7), rows(&names(26), 1));
+        assert_eq!(rows(&names(3), 2), rows(&names(3), 0));
+    }
+
+    #[test]
+    fn every_button_has_its_own_id() {
+        let names = names(60);
+        for page in 0..controller_pages(names.len()) {
+            let ids: Vec<String> = rows(&names, page)
+                .into_iter()
+                .flatten()
+                .map(|(id, _, _)| id)
+                .collect();
+            let unique: HashSet<&String> = ids.iter().collect();
+            assert_eq!(unique.len(), ids.len());
+            assert!(ids.iter().all(|id| id.len() <= MAX_BUTTON_ID_LEN));
+        }
+    }
+
+    #[test]
+    fn controllers_load_what_they_save() {
+        let mut channels: HashMap<u64, HashSet<u64>> = HashMap::new();
+        channels.insert(1, vec![10, 11].into_iter().collect());
+        channels.insert(2, vec![20].into_iter().collect());
+        let controllers = Controllers::load(&channels);
+        assert_eq!(controllers.panels.len(), 3);
+        // Pages aren't saved, so everything starts on the first
+        assert_eq!(controllers.panels[&MessageId(11)], (ChannelId(1), 0));
+        assert_eq!(controllers.channels(), channels);
+    }
+
+    fn emoji(text: &str) -> ReactionType {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn emoji_match_with_or_without_the_variation_selector() {
+        assert!(same_emoji(&emoji("⬆\u{fe0f}"), &emoji("⬆")));
+        assert!(same_emoji(&emoji("⬆"), &emoji("⬆\u{fe0f}")));
+        assert!(!same_emoji(&emoji("⬆"), &emoji("⬇")));
+    }
+
+    #[test]
+    fn custom_emoji_match_by_id() {
+        assert!(same_emoji(&emoji("<:jump:123>"), &emoji("<:hop:123>")));
+        assert!(same_emoji(&emoji("<a:jump:123>"), &emoji("<:jump:123>")));
+        assert!(!same_emoji(&emoji("<:jump:123>"), &emoji("<:jump:456>")));
+        assert!(!same_emoji(&emoji("<:jump:123>"), &emoji("⬆")));
+    }
+
+    #[test]
+    fn only_20_reactions_fit() {
+        let pairs: Vec<(ReactionType, String)> = (0..22)
+            .map(|index| {
+                (
+                    emoji(&format!("<:e{}:{}>", index, index + 1)),
+                    format!("Action{}", index),
+                )
+            })
+            .collect();
+        let text = reaction_controller_text(&pairs);
+        assert!(text.starts_with("React to play:\n<:e0:1> Action0"));
+        assert!(text.contains("Action19"));
+        assert!(!text.contains("Action20"));
+        assert!(
+            text.ends_with("(Only 20 reactions fit on a message, so 2 more can't be used here.)")
+        );
+        assert_eq!(
+            reaction_controller_text(&[]),
+            "No actions have an emoji right now."
+        );
+
+        let (remove, add) = reaction_changes(&[], &pairs);
+        assert!(remove.is_empty());
+        assert_eq!(add.len(), 20);
+    }
+
+    #[test]
+    fn only_changed_reactions_are_synced() {
+        let pairs = vec![
+            (emoji("⬆\u{fe0f}"), "Up".to_string()),
+            (emoji("⬇"), "Down".to_string()),
+        ];
+        let ours = vec![emoji("⬆"), emoji("<:old:1>")];
+        let (remove, add) = reaction_changes(&ours, &pairs);
+        assert_eq!(remove, [emoji("<:old:1>")]);
+        assert_eq!(add, [&pairs[1]]);
+    }
+
+    #[test]
+    fn removing_the_last_reaction_controller_forgets_the_channel() {
+        let mut controllers = ReactionControllers::default();
+        controllers
+            .channels
+            .insert(1, vec![10, 11].into_iter().collect());
+        controllers.remove(ChannelId(1), MessageId(10));
+        assert!(controllers.contains(ChannelId(1), MessageId(11)));
+        assert!(!controllers.contains(ChannelId(1), MessageId(10)));
+        controllers.remove(ChannelId(1), MessageId(11));
+        assert!(controllers.channels.is_empty());
+        // Removing what isn't there is fine
+        controllers.remove(ChannelId(2), MessageId(20));
+    }
+
+    #[test]
+    fn play_channels_are_checked_per_guild() {
+        let mut play_channels = PlayChannels::default();
+        play_channels
+            .guilds
+            .insert(1, vec![10, 11].into_iter().collect());
+        assert!(play_channels.contains(GuildId(1), ChannelId(10)));
+        assert!(!play_channels.contains(GuildId(1), ChannelId(12)));
+        // The same channel ID in another guild doesn't count
+        assert!(!play_channels.contains(GuildId(2), ChannelId(10)));
+    }
+
+    #[test]
+    fn play_channels_load_what_they_save() {
+        let dir = std::env::temp_dir().join("discord_plays_play_channels");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, config::DEFAULT_CONFIG).unwrap();
+
+        let mut saved = PlayChannels::default();
+        saved.guilds.insert(1, vec![10, 11].into_iter().collect());
+        saved.guilds.insert(2, vec![20].into_iter().collect());
+        config::save_play_channels(&path, &saved.guilds).unwrap();
+
+        let config = Config::parse(
+            &std::fs::read_to_string(&path).unwrap(),
+            Some("token".to_string()),
+        )
+        .unwrap();
+        let loaded = PlayChannels {
+            guilds: config.play_channels,
+        };
+        assert_eq!(loaded.guilds, saved.guilds);
+        assert!(loaded.contains(GuildId(1), ChannelId(11)));
+        assert!(loaded.contains(GuildId(2), ChannelId(20)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn automatic_democracy() -> Democracy {
+        Democracy {
+            mode: InputMode::Anarchy,
+            automatic: true,
+            pressure: RateMeter::new(PRESSURE_WINDOW),
+            switch: Hysteresis::new(DEFAULT_ANARCHY_BELOW, DEFAULT_DEMOCRACY_ABOVE),
+            window: DEFAULT_VOTE_WINDOW,
+            tally: None,
+        }
+    }
+
+    #[test]
+    fn busy_chat_switches_to_democracy() {
+        let start = Instant::now();
+        let mut democracy = automatic_democracy();
+        for _ in 0..20 {
+            assert!(democracy.record_action(start) == InputMode::Anarchy);
+        }
+        assert!(democracy.record_action(start) == InputMode::Democracy);
+    }
+
+    #[test]
+    fn quiet_chat_switches_back_without_new_actions() {
+        let start = Instant::now();
+        let mut democracy = automatic_democracy();
+        for _ in 0..30 {
+            democracy.record_action(start);
+        }
+        assert!(democracy.refresh(start) == InputMode::Democracy);
+        assert!(democracy.refresh(start + PRESSURE_WINDOW * 2) == InputMode::Anarchy);
+        assert!(democracy.mode == InputMode::Anarchy);
+    }
+
+    #[test]
+    fn refreshing_leaves_a_picked_mode_alone() {
+        let start = Instant::now();
+        let mut democracy = automatic_democracy();
+        democracy.automatic = false;
+        democracy.mode = InputMode::Democracy;
+        assert!(democracy.refresh(start + PRESSURE_WINDOW * 2) == InputMode::Democracy);
+    }
+
+    #[tokio::test]
+    async fn only_https_links_are_downloaded() {
+        let urls = [
+            "http://example.com/avatar.png",
+            "file:///etc/passwd",
+            "avatar.png",
+        ];
+        for url in &urls {
+            let result = download(url).await;
+            assert!(matches!(result, Err(DownloadError::NotHttps)), "{}", url);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::roles::{Level, RoleRef};
+
+// What someone looks like to the permission checks
+pub struct Member<'a> {
+    pub id: u64,
+    // Each role's ID and name
+    pub roles: &'a [(u64, String)],
+    // Has the Administrator permission, or owns the server
+    pub administrator: bool,
+}
+
+// Works out everyone's level from their roles, their Discord permissions
+// and any levels given out with `grant`.
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    pub owners: Vec<RoleRef>,
+    pub mods: Vec<RoleRef>,
+    pub managers: Vec<RoleRef>,
+    // The bot's owners on Discord, who can never lose owner
+    pub bot_owners: HashSet<u64>,
+    // Levels given to user or role IDs. Discord never reuses an ID
+    // between users and roles, so they can share a map.
+    grants: HashMap<u64, Level>,
+}
+
+impl Access {
+    pub fn new(
+        owners: Vec<RoleRef>,
+        mods: Vec<RoleRef>,
+        managers: Vec<RoleRef>,
+        grants: HashMap<u64, Level>,
+    ) -> Access {
+        Access {
+            owners,
+            mods,
+            managers,
+            bot_owners: HashSet::new(),
+            grants,
+        }
+    }
+
+    pub fn level(&self, member: &Member) -> Level {
+        if self.bot_owners.contains(&member.id) {
+            return Level::Owner;
+        }
+        let mut level = Level::Player;
+        if member.administrator {
+            level = Level::Mod;
+        }
+        if let Some(granted) = self.grants.get(&member.id) {
+            level = level.max(*granted);
+        }
+        for (id, name) in member.roles {
+            if let Some(granted) = self.grants.get(id) {
+                level = level.max(*granted);
+            }
+            for (role_level, roles) in [
+                (Level::Owner, &self.owners),
+                (Level::Mod, &self.mods),
+                (Level::Manager, &self.managers),
+            ] {
+                if roles.iter().any(|role| role.matches(*id, name)) {
+                    level = level.max(role_level);
+                }
+            }
+        }
+        level
+    }
+
+    // Whether someone can use an action that needs `level`, and one of `roles` if there are any.
+    // Mods can use every action.
+    pub fn can_use(&self, member: &Member, level: Level, roles: &[RoleRef]) -> bool {
+        let member_level = self.level(member);
+        if member_level < level {
+            return false;
+        }
+        roles.is_empty()
+            || member_level >= Level::Mod
+            || member
+                .roles
+                .iter()
+                .any(|(id, name)| roles.iter().any(|role| role.matches(*id, name)))
+    }
+
+    pub fn grant(&mut self, id: u64, level: Level) {
+        if level == Level::Player {
+            self.grants.remove(&id);
+        } else {
+            self.grants.insert(id, level);
+        }
+    }
+
+    // Returns the level that was taken away, if there was one
+    pub fn revoke(&mut self, id: u64) -> Option<Level> {
+        self.grants.remove(&id)
+    }
+
+    pub fn grants(&self) -> &HashMap<u64, Level> {
+        &self.grants
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roles(roles: &[(u64, &str)]) -> Vec<(u64, String)> {
+        roles
+            .iter()
+            .map(|(id, name)| (*id, name.to_string()))
+            .collect()
+    }
+
+    fn member(id: u64, roles: &[(u64, String)]) -> Member<'_> {
+        Member {
+            id,
+            roles,
+            administrator: false,
+        }
+    }
+
+    fn access() -> Access {
+        Access::new(
+            vec![RoleRef::Id(900)],
+            vec![RoleRef::new("Mods")],
+            vec![RoleRef::new("Discord Plays Manager")],
+            HashMap::new(),
+        )
+    }
+
+    #[test]
+    fn the_highest_role_counts() {
+        let access = access();
+        let nobody = roles(&[(1, "Everyone")]);
+        assert_eq!(access.level(&member(5, &nobody)), Level::Player);
+        let manager = roles(&[(2, "Discord Plays Manager")]);
+        assert_eq!(access.level(&member(5, &manager)), Level::Manager);
+        let both = roles(&[(2, "Discord Plays Manager"), (3, "Mods")]);
+        assert_eq!(access.level(&member(5, &both)), Level::Mod);
+        // Roles in the config can be IDs, whatever the role is called
+        let owner = roles(&[(900, "Renamed")]);
+        assert_eq!(access.level(&member(5, &owner)), Level::Owner);
+    }
+
+    #[test]
+    fn administrators_and_bot_owners_are_trusted() {
+        let mut access = access();
+        let administrator = Member {
+            id: 5,
+            roles: &[],
+            administrator: true,
+        };
+        assert_eq!(access.level(&administrator), Level::Mod);
+        access.bot_owners.insert(6);
+        assert_eq!(access.level(&member(6, &[])), Level::Owner);
+    }
+
+    #[test]
+    fn grants_raise_levels_and_revoke_takes_them_away() {
+        let mut access = access();
+        let role = roles(&[(40, "Helpers")]);
+        access.grant(40, Level::Manager);
+        access.grant(5, Level::Mod);
+        assert_eq!(access.level(&member(5, &role)), Level::Mod);
+        assert_eq!(access.level(&member(6, &role)), Level::Manager);
+        assert_eq!(access.revoke(5), Some(Level::Mod));
+        assert_eq!(access.revoke(5), None);
+        assert_eq!(access.level(&member(5, &role)), Level::Manager);
+        // Granting player is the same as revoking
+        access.grant(40, Level::Player);
+        assert!(access.grants().is_empty());
+    }
+
+    #[test]
+    fn grants_never_lower_a_level() {
+        let mut access = access();
+        access.grant(5, Level::Manager);
+        let mods = roles(&[(3, "Mods")]);
+        assert_eq!(access.level(&member(5, &mods)), Level::Mod);
+    }
+
+    #[test]
+    fn restricted_actions_need_the_level_and_a_role() {
+        let access = access();
+        let trusted = roles(&[(7, "Trusted")]);
+        let nobody = roles(&[]);
+        let mods = roles(&[(3, "Mods")]);
+        let needs_trusted = [RoleRef::new("Trusted")];
+        assert!(access.can_use(&member(5, &trusted), Level::Player, &needs_trusted));
+        assert!(!access.can_use(&member(5, &nobody), Level::Player, &needs_trusted));
+        assert!(!access.can_use(&member(5, &trusted), Level::Manager, &needs_trusted));
+        // Mods can use everything their level allows, with or without the roles
+        assert!(access.can_use(&member(5, &mods), Level::Mod, &needs_trusted));
+        assert!(!access.can_use(&member(5, &mods), Level::Owner, &[]));
+    }
+}
@@ -0,0 +1,286 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{permissions::Member, roles::RoleRef};
+
+// Decides who can send input at all: blocked players never can, and with the
+// allowlist on only the users and roles on it can.
+// Blocks use the wall clock, so they can be saved and still end on time after a restart.
+#[derive(Clone, Debug, Default)]
+pub struct PlayerFilter {
+    // When each block ends, or None for blocks that don't
+    blocked: HashMap<u64, Option<SystemTime>>,
+    pub allowlist: bool,
+    // User IDs, role IDs or role names
+    pub allowed: Vec<RoleRef>,
+}
+
+impl PlayerFilter {
+    pub fn new(
+        blocked: HashMap<u64, Option<SystemTime>>,
+        allowlist: bool,
+        allowed: Vec<RoleRef>,
+    ) -> PlayerFilter {
+        PlayerFilter {
+            blocked,
+            allowlist,
+            allowed,
+        }
+    }
+
+    pub fn block(&mut self, user: u64, until: Option<SystemTime>) {
+        self.blocked.insert(user, until);
+    }
+
+    // Returns false if they weren't blocked
+    pub fn unblock(&mut self, user: u64) -> bool {
+        self.blocked.remove(&user).is_some()
+    }
+
+    pub fn is_blocked(&self, user: u64, now: SystemTime) -> bool {
+        match self.blocked.get(&user) {
+            Some(Some(until)) => *until > now,
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    // Forgets blocks that are over. Returns true if there were any.
+    pub fn remove_expired(&mut self, now: SystemTime) -> bool {
+        let before = self.blocked.len();
+        self.blocked
+            .retain(|_, until| until.is_none_or(|until| until > now));
+        self.blocked.len() != before
+    }
+
+    pub fn blocked(&self) -> &HashMap<u64, Option<SystemTime>> {
+        &self.blocked
+    }
+
+    pub fn is_on_allowlist(&self, member: &Member) -> bool {
+        self.allowed.iter().any(|allowed| {
+            *allowed == RoleRef::Id(member.id)
+                || member
+                    .roles
+                    .iter()
+                    .any(|(id, name)| allowed.matches(*id, name))
+        })
+    }
+
+    // Whether this member can send input right now
+    pub fn can_play(&self, member: &Member, now: SystemTime) -> bool {
+        !self.is_blocked(member.id, now) && (!self.allowlist || self.is_on_allowlist(member))
+    }
+
+    pub fn allow(&mut self, target: RoleRef) {
+        if !self.allowed.contains(&target) {
+            self.allowed.push(target);
+        }
+    }
+
+    // Returns false if it wasn't on the allowlist
+    pub fn disallow(&mut self, target: &RoleRef) -> bool {
+        let before = self.allowed.len();
+        self.allowed.retain(|allowed| allowed != target);
+        self.allowed.len() != before
+    }
+}
+
+// When Discord started counting, in milliseconds since the Unix epoch
+const DISCORD_EPOCH_MS: u64 = 1_420_070_400_000;
+
+// When an account was made, worked out from its ID
+pub fn account_created(user: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis((user >> 22) + DISCORD_EPOCH_MS)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TooNew {
+    // How much longer until the account is old enough
+    Account(Duration),
+    // How much longer until they have been in the server long enough
+    Membership(Duration),
+}
+
+// Keeps brand new accounts, like raid alts, from playing straight away.
+#[derive(Clone, Debug, Default)]
+pub struct AgeGate {
+    pub min_account_age: Duration,
+    pub min_membership: Duration,
+    // Players who were already told why they can't play
+    told: HashSet<u64>,
+}
+
+impl AgeGate {
+    pub fn new(min_account_age: Duration, min_membership: Duration) -> AgeGate {
+        AgeGate {
+            min_account_age,
+            min_membership,
+            told: HashSet::new(),
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.min_account_age > Duration::ZERO || self.min_membership > Duration::ZERO
+    }
+
+    // `joined` is when they joined the server, if that is known.
+    // If it isn't, they count as having only just joined.
+    pub fn check(
+        &self,
+        user: u64,
+        joined: Option<SystemTime>,
+        now: SystemTime,
+    ) -> Result<(), TooNew> {
+        let account_age = now
+            .duration_since(account_created(user))
+            .unwrap_or_default();
+        if account_age < self.min_account_age {
+            return Err(TooNew::Account(self.min_account_age - account_age));
+        }
+        let membership = joined
+            .map(|joined| now.duration_since(joined).unwrap_or_default())
+            .unwrap_or_default();
+        if membership < self.min_membership {
+            return Err(TooNew::Membership(self.min_membership - membership));
+        }
+        Ok(())
+    }
+
+    // Returns true the first time it is called for a player, so they are only told once
+    pub fn should_tell(&mut self, user: u64) -> bool {
+        self.told.insert(user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    // Made at the Discord epoch, so years old by now
+    const OLD_ACCOUNT: u64 = 1;
+
+    fn now() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(DISCORD_EPOCH_MS) + 1000 * DAY
+    }
+
+    // An account made `age` before `now()`
+    fn account_aged(age: Duration) -> u64 {
+        let created = now().duration_since(account_created(0)).unwrap() - age;
+        (created.as_millis() as u64) << 22
+    }
+
+    fn member(id: u64, roles: &[(u64, String)]) -> Member<'_> {
+        Member {
+            id,
+            roles,
+            administrator: false,
+        }
+    }
+
+    #[test]
+    fn blocks_end_on_time() {
+        let mut filter = PlayerFilter::default();
+        filter.block(1, Some(now() + DAY));
+        filter.block(2, None);
+        assert!(filter.is_blocked(1, now()));
+        assert!(!filter.is_blocked(1, now() + DAY));
+        assert!(filter.is_blocked(2, now() + 1000 * DAY));
+        assert!(!filter.is_blocked(3, now()));
+
+        assert!(!filter.remove_expired(now()));
+        assert!(filter.remove_expired(now() + DAY));
+        assert_eq!(filter.blocked().len(), 1);
+        assert!(filter.unblock(2));
+        assert!(!filter.unblock(2));
+    }
+
+    #[test]
+    fn the_allowlist_takes_users_and_roles() {
+        let mut filter = PlayerFilter::default();
+        let vip = vec![(50, "VIP".to_string())];
+        let nobody = vec![];
+        // Off, everyone can play
+        assert!(filter.can_play(&member(1, &nobody), now()));
+
+        filter.allowlist = true;
+        filter.allow(RoleRef::new("VIP"));
+        filter.allow(RoleRef::Id(2));
+        filter.allow(RoleRef::Id(2));
+        assert_eq!(filter.allowed.len(), 2);
+        assert!(filter.can_play(&member(1, &vip), now()));
+        assert!(filter.can_play(&member(2, &nobody), now()));
+        assert!(!filter.can_play(&member(3, &nobody), now()));
+
+        assert!(filter.disallow(&RoleRef::new("VIP")));
+        assert!(!filter.disallow(&RoleRef::new("VIP")));
+        assert!(!filter.can_play(&member(1, &vip), now()));
+    }
+
+    #[test]
+    fn blocks_beat_the_allowlist() {
+        let mut filter = PlayerFilter::new(HashMap::new(), true, vec![RoleRef::Id(1)]);
+        filter.block(1, None);
+        assert!(!filter.can_play(&member(1, &[]), now()));
+    }
+
+    #[test]
+    fn account_age_comes_from_the_id() {
+        assert_eq!(
+            account_created(0),
+            UNIX_EPOCH + Duration::from_millis(DISCORD_EPOCH_MS)
+        );
+        assert_eq!(account_created(account_aged(DAY)), now() - DAY);
+    }
+
+    #[test]
+    fn new_accounts_wait_for_the_rest_of_the_age() {
+        let gate = AgeGate::new(7 * DAY, Duration::ZERO);
+        assert_eq!(
+            gate.check(account_aged(2 * DAY), None, now()),
+            Err(TooNew::Account(5 * DAY))
+        );
+        assert_eq!(gate.check(account_aged(7 * DAY), None, now()), Ok(()));
+    }
+
+    #[test]
+    fn new_members_wait_for_the_rest_of_the_membership() {
+        let gate = AgeGate::new(Duration::ZERO, 3 * DAY);
+        assert_eq!(
+            gate.check(OLD_ACCOUNT, Some(now() - DAY), now()),
+            Err(TooNew::Membership(2 * DAY))
+        );
+        assert_eq!(
+            gate.check(OLD_ACCOUNT, Some(now() - 3 * DAY), now()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn unknown_join_times_dont_pass_the_membership_gate() {
+        let gate = AgeGate::new(Duration::ZERO, 3 * DAY);
+        assert_eq!(
+            gate.check(OLD_ACCOUNT, None, now()),
+            Err(TooNew::Membership(3 * DAY))
+        );
+        // With no membership gate, nobody needs to know when they joined
+        assert_eq!(
+            AgeGate::new(DAY, Duration::ZERO).check(OLD_ACCOUNT, None, now()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn players_are_only_told_once() {
+        let mut gate = AgeGate::new(DAY, DAY);
+        assert!(gate.is_on());
+        assert!(gate.should_tell(5));
+        assert!(!gate.should_tell(5));
+        assert!(gate.should_tell(6));
+        assert!(!AgeGate::default().is_on());
+    }
+}
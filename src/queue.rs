@@ -0,0 +1,260 @@
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex},
+};
+
+use serde::Deserialize;
+
+use crate::chain;
+
+// What to do with a new action when the queue is already full
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropPolicy {
+    // Ignore the new action
+    DropNewest,
+    // Make room by throwing out the action that has waited longest
+    DropOldest,
+    // Ignore the new action, and let whoever sent it know
+    Reject,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueuedAction {
+    // Usually one action. Several actions from one message stay together,
+    // and run one after another with nothing in between.
+    pub actions: Vec<String>,
+    pub requested_by: String,
+}
+
+impl QueuedAction {
+    // Like `left*3 jump`
+    pub fn name(&self) -> String {
+        chain::describe(&self.actions)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PushResult {
+    Queued,
+    DroppedOldest(QueuedAction),
+    DroppedNewest,
+    Rejected,
+}
+
+struct QueueState {
+    items: VecDeque<QueuedAction>,
+    running: Option<QueuedAction>,
+    interrupted: bool,
+    max_len: usize,
+    policy: DropPolicy,
+}
+
+// First in, first out list of actions waiting for the executor.
+pub struct ActionQueue {
+    state: Mutex<QueueState>,
+    available: Condvar,
+}
+
+impl ActionQueue {
+    pub fn new(max_len: usize, policy: DropPolicy) -> ActionQueue {
+        ActionQueue {
+            state: Mutex::new(QueueState {
+                items: VecDeque::new(),
+                running: None,
+                interrupted: false,
+                max_len,
+                policy,
+            }),
+            available: Condvar::new(),
+        }
+    }
+
+    pub fn set_limits(&self, max_len: usize, policy: DropPolicy) {
+        let mut state = self.state.lock().unwrap();
+        state.max_len = max_len;
+        state.policy = policy;
+        while state.items.len() > max_len {
+            state.items.pop_back();
+        }
+    }
+
+    pub fn push(&self, item: QueuedAction) -> PushResult {
+        let mut state = self.state.lock().unwrap();
+        let mut result = PushResult::Queued;
+        if state.items.len() >= state.max_len {
+            match state.policy {
+                DropPolicy::DropNewest => return PushResult::DroppedNewest,
+                DropPolicy::Reject => return PushResult::Rejected,
+                DropPolicy::DropOldest => match state.items.pop_front() {
+                    Some(oldest) => result = PushResult::DroppedOldest(oldest),
+                    // A queue with no room at all
+                    None => return PushResult::DroppedNewest,
+                },
+            }
+        }
+        state.items.push_back(item);
+        self.available.notify_one();
+        result
+    }
+
+    // Waits for the next action, and marks it as running.
+    // Returns None early if something called `interrupt`.
+    pub fn pop_blocking(&self) -> Option<QueuedAction> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.interrupted {
+                state.interrupted = false;
+                return None;
+            }
+            if let Some(item) = state.items.pop_front() {
+                state.running = Some(item.clone());
+                return Some(item);
+            }
+            state = self.available.wait(state).unwrap();
+        }
+    }
+
+    // Wakes up whoever is waiting in `pop_blocking`, even with nothing queued
+    pub fn interrupt(&self) {
+        self.state.lock().unwrap().interrupted = true;
+        self.available.notify_all();
+    }
+
+    // Called by the executor once the running action is over
+    pub fn finish(&self) {
+        self.state.lock().unwrap().running = None;
+    }
+
+    pub fn running(&self) -> Option<QueuedAction> {
+        self.state.lock().unwrap().running.clone()
+    }
+
+    pub fn waiting(&self) -> Vec<QueuedAction> {
+        self.state.lock().unwrap().items.iter().cloned().collect()
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.state.lock().unwrap().max_len
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Removes the next action that would have run
+    pub fn skip(&self) -> Option<QueuedAction> {
+        self.state.lock().unwrap().items.pop_front()
+    }
+
+    // Returns how many actions were removed
+    pub fn clear(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let removed = state.items.len();
+        state.items.clear();
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(action: &str) -> QueuedAction {
+        QueuedAction {
+            actions: vec![action.to_string()],
+            requested_by: "player".to_string(),
+        }
+    }
+
+    fn names(queue: &ActionQueue) -> Vec<String> {
+        queue.waiting().iter().map(QueuedAction::name).collect()
+    }
+
+    #[test]
+    fn actions_come_out_in_order() {
+        let queue = ActionQueue::new(5, DropPolicy::Reject);
+        queue.push(item("a"));
+        queue.push(item("b"));
+        assert_eq!(queue.pop_blocking(), Some(item("a")));
+        assert_eq!(queue.running(), Some(item("a")));
+        assert_eq!(names(&queue), ["b"]);
+        queue.finish();
+        assert_eq!(queue.running(), None);
+    }
+
+    #[test]
+    fn full_queues_reject_or_drop_newest() {
+        for (policy, result) in [
+            (DropPolicy::Reject, PushResult::Rejected),
+            (DropPolicy::DropNewest, PushResult::DroppedNewest),
+        ] {
+            let queue = ActionQueue::new(2, policy);
+            assert_eq!(queue.push(item("a")), PushResult::Queued);
+            assert_eq!(queue.push(item("b")), PushResult::Queued);
+            assert_eq!(queue.push(item("c")), result);
+            assert_eq!(names(&queue), ["a", "b"]);
+        }
+    }
+
+    #[test]
+    fn full_queues_can_drop_the_oldest() {
+        let queue = ActionQueue::new(2, DropPolicy::DropOldest);
+        queue.push(item("a"));
+        queue.push(item("b"));
+        assert_eq!(queue.push(item("c")), PushResult::DroppedOldest(item("a")));
+        assert_eq!(names(&queue), ["b", "c"]);
+
+        // With no room at all there is nothing to drop
+        let queue = ActionQueue::new(0, DropPolicy::DropOldest);
+        assert_eq!(queue.push(item("a")), PushResult::DroppedNewest);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn shrinking_the_queue_drops_the_newest() {
+        let queue = ActionQueue::new(3, DropPolicy::Reject);
+        queue.push(item("a"));
+        queue.push(item("b"));
+        queue.push(item("c"));
+        queue.set_limits(1, DropPolicy::DropOldest);
+        assert_eq!(names(&queue), ["a"]);
+        assert_eq!(queue.max_len(), 1);
+        assert_eq!(queue.push(item("d")), PushResult::DroppedOldest(item("a")));
+    }
+
+    #[test]
+    fn skip_and_clear_leave_the_running_action() {
+        let queue = ActionQueue::new(5, DropPolicy::Reject);
+        for action in &["a", "b", "c", "d"] {
+            queue.push(item(action));
+        }
+        queue.pop_blocking();
+        assert_eq!(queue.skip(), Some(item("b")));
+        assert_eq!(queue.clear(), 2);
+        assert_eq!(queue.skip(), None);
+        assert_eq!(queue.running(), Some(item("a")));
+    }
+
+    #[test]
+    fn interrupt_wakes_an_empty_queue_once() {
+        let queue = ActionQueue::new(5, DropPolicy::Reject);
+        queue.interrupt();
+        assert_eq!(queue.pop_blocking(), None);
+        queue.push(item("a"));
+        assert_eq!(queue.pop_blocking(), Some(item("a")));
+    }
+
+    #[test]
+    fn chains_are_named_like_they_were_typed() {
+        let chain = QueuedAction {
+            actions: vec!["left".to_string(), "left".to_string(), "jump".to_string()],
+            requested_by: "player".to_string(),
+        };
+        assert_eq!(chain.name(), "left*2 jump");
+    }
+}
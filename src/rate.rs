@@ -0,0 +1,126 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// Counts how many things happened recently, e.g. how busy chat is.
+pub struct RateMeter {
+    window: Duration,
+    times: VecDeque<Instant>,
+}
+
+impl RateMeter {
+    // Only events from the last `window` count towards the rate
+    pub fn new(window: Duration) -> RateMeter {
+        RateMeter {
+            window,
+            times: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, now: Instant) {
+        self.forget_old(now);
+        self.times.push_back(now);
+    }
+
+    // Average events per second over the window
+    pub fn per_second(&mut self, now: Instant) -> f64 {
+        self.forget_old(now);
+        self.times.len() as f64 / self.window.as_secs_f64()
+    }
+
+    fn forget_old(&mut self, now: Instant) {
+        while let Some(oldest) = self.times.front() {
+            if now.saturating_duration_since(*oldest) > self.window {
+                self.times.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+// Turns on above `high` and only turns off again below `low`,
+// so a value hovering around one threshold doesn't make it flip back and forth.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hysteresis {
+    pub low: f64,
+    pub high: f64,
+    on: bool,
+}
+
+impl Hysteresis {
+    pub fn new(low: f64, high: f64) -> Hysteresis {
+        Hysteresis {
+            low: low.min(high),
+            high: low.max(high),
+            on: false,
+        }
+    }
+
+    // Moves the thresholds without turning it on or off
+    pub fn set_thresholds(&mut self, low: f64, high: f64) {
+        self.low = low.min(high);
+        self.high = low.max(high);
+    }
+
+    // Returns whether it is on after seeing `value`
+    pub fn update(&mut self, value: f64) -> bool {
+        if !self.on && value > self.high {
+            self.on = true;
+        } else if self.on && value < self.low {
+            self.on = false;
+        }
+        self.on
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_recent_events_count() {
+        let start = Instant::now();
+        let mut meter = RateMeter::new(Duration::from_secs(2));
+        meter.record(start);
+        meter.record(start + Duration::from_secs(1));
+        assert_eq!(meter.per_second(start + Duration::from_secs(1)), 1.0);
+        // Exactly `window` old still counts, anything older doesn't
+        assert_eq!(meter.per_second(start + Duration::from_secs(2)), 1.0);
+        assert_eq!(meter.per_second(start + Duration::from_millis(2001)), 0.5);
+        assert_eq!(meter.per_second(start + Duration::from_secs(4)), 0.0);
+    }
+
+    #[test]
+    fn hysteresis_only_flips_past_the_thresholds() {
+        let mut switch = Hysteresis::new(1.0, 3.0);
+        assert!(!switch.update(3.0));
+        assert!(switch.update(3.1));
+        // Between the thresholds it stays how it was
+        assert!(switch.update(2.0));
+        assert!(switch.update(1.0));
+        assert!(!switch.update(0.9));
+        assert!(!switch.update(2.0));
+    }
+
+    #[test]
+    fn thresholds_are_put_in_order() {
+        let switch = Hysteresis::new(3.0, 1.0);
+        assert_eq!((switch.low, switch.high), (1.0, 3.0));
+    }
+
+    #[test]
+    fn moving_thresholds_keeps_it_on() {
+        let mut switch = Hysteresis::new(1.0, 3.0);
+        switch.update(4.0);
+        switch.set_thresholds(5.0, 10.0);
+        assert!(switch.is_on());
+        assert_eq!((switch.low, switch.high), (5.0, 10.0));
+        assert!(!switch.update(4.0));
+    }
+}
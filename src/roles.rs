@@ -0,0 +1,119 @@
+// Who can do what, as written in config.toml and actions.txt.
+// Working out who someone actually is happens in `permissions`.
+
+use std::{fmt, str::FromStr};
+
+// How much someone is trusted with, from least to most
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    #[default]
+    Player,
+    Manager,
+    Mod,
+    Owner,
+}
+
+pub const LEVEL_NAMES: [&str; 4] = ["player", "manager", "mod", "owner"];
+
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Player => "player",
+            Level::Manager => "manager",
+            Level::Mod => "mod",
+            Level::Owner => "owner",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Level {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Level, ()> {
+        match name.to_lowercase().as_str() {
+            "player" => Ok(Level::Player),
+            "manager" => Ok(Level::Manager),
+            "mod" => Ok(Level::Mod),
+            "owner" => Ok(Level::Owner),
+            _ => Err(()),
+        }
+    }
+}
+
+// A role written as a name or as an ID
+#[derive(Clone, Debug, PartialEq)]
+pub enum RoleRef {
+    Id(u64),
+    Name(String),
+}
+
+impl RoleRef {
+    pub fn new(role: &str) -> RoleRef {
+        match role.parse() {
+            Ok(id) => RoleRef::Id(id),
+            Err(_) => RoleRef::Name(role.to_string()),
+        }
+    }
+
+    // How it is written in the config file
+    pub fn config_name(&self) -> String {
+        match self {
+            RoleRef::Id(id) => id.to_string(),
+            RoleRef::Name(name) => name.clone(),
+        }
+    }
+
+    pub fn matches(&self, id: u64, name: &str) -> bool {
+        match self {
+            RoleRef::Id(role_id) => *role_id == id,
+            RoleRef::Name(role_name) => role_name == name,
+        }
+    }
+}
+
+// IDs are shown as role mentions, which Discord shows as the role's name
+impl fmt::Display for RoleRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoleRef::Id(id) => write!(f, "<@&{}>", id),
+            RoleRef::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_go_up_in_order() {
+        assert!(Level::Player < Level::Manager);
+        assert!(Level::Manager < Level::Mod);
+        assert!(Level::Mod < Level::Owner);
+        for name in &LEVEL_NAMES {
+            assert_eq!(name.parse::<Level>().unwrap().name(), *name);
+        }
+        assert_eq!("MOD".parse(), Ok(Level::Mod));
+        assert_eq!("admin".parse::<Level>(), Err(()));
+    }
+
+    #[test]
+    fn roles_are_ids_or_names() {
+        assert_eq!(
+            RoleRef::new("759786910427381790"),
+            RoleRef::Id(759786910427381790)
+        );
+        assert_eq!(RoleRef::new("Mods"), RoleRef::Name("Mods".to_string()));
+        assert!(RoleRef::new("123").matches(123, "Anything"));
+        assert!(RoleRef::new("Mods").matches(1, "Mods"));
+        assert!(!RoleRef::new("Mods").matches(1, "mods"));
+        assert_eq!(RoleRef::Id(5).to_string(), "<@&5>");
+        assert_eq!(RoleRef::Id(5).config_name(), "5");
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use rand::{seq::SliceRandom, Rng};
+
+// Votes for the next action during one democracy window.
+// Everyone gets one vote, and voting again changes it.
+#[derive(Clone, Debug, Default)]
+pub struct VoteTally {
+    votes: HashMap<u64, String>,
+}
+
+impl VoteTally {
+    pub fn new() -> VoteTally {
+        VoteTally::default()
+    }
+
+    pub fn vote(&mut self, voter: u64, action: &str) {
+        self.votes.insert(voter, action.to_string());
+    }
+
+    pub fn voters(&self) -> usize {
+        self.votes.len()
+    }
+
+    // Everyone who voted for an action
+    pub fn voters_for(&self, action: &str) -> Vec<u64> {
+        self.votes
+            .iter()
+            .filter(|(_, vote)| *vote == action)
+            .map(|(voter, _)| *voter)
+            .collect()
+    }
+
+    // Every action that got a vote, most votes first
+    pub fn results(&self) -> Vec<(String, usize)> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for action in self.votes.values() {
+            *counts.entry(action).or_insert(0) += 1;
+        }
+        let mut results: Vec<(String, usize)> = counts
+            .into_iter()
+            .map(|(action, count)| (action.to_string(), count))
+            .collect();
+        results.sort_by(|(a_name, a_count), (b_name, b_count)| {
+            b_count.cmp(a_count).then_with(|| a_name.cmp(b_name))
+        });
+        results
+    }
+
+    // Every action tied for the most votes
+    pub fn leaders(&self) -> Vec<String> {
+        let results = self.results();
+        let most = results.first().map_or(0, |(_, count)| *count);
+        results
+            .into_iter()
+            .filter(|(_, count)| *count == most)
+            .map(|(action, _)| action)
+            .collect()
+    }
+
+    // The action with the most votes, picking randomly between ties
+    pub fn winner<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<String> {
+        self.leaders().choose(rng).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn voting_again_changes_the_vote() {
+        let mut tally = VoteTally::new();
+        tally.vote(1, "left");
+        tally.vote(2, "left");
+        tally.vote(1, "jump");
+        assert_eq!(tally.voters(), 2);
+        assert_eq!(tally.voters_for("left"), [2]);
+        assert_eq!(
+            tally.results(),
+            [("jump".to_string(), 1), ("left".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn the_most_votes_win() {
+        let mut tally = VoteTally::new();
+        tally.vote(1, "left");
+        tally.vote(2, "jump");
+        tally.vote(3, "jump");
+        assert_eq!(tally.results()[0], ("jump".to_string(), 2));
+        assert_eq!(tally.leaders(), ["jump"]);
+        assert_eq!(
+            tally.winner(&mut StdRng::seed_from_u64(0)),
+            Some("jump".to_string())
+        );
+    }
+
+    #[test]
+    fn ties_are_broken_between_the_leaders() {
+        let mut tally = VoteTally::new();
+        tally.vote(1, "left");
+        tally.vote(2, "right");
+        tally.vote(3, "jump");
+        tally.vote(4, "jump");
+        tally.vote(5, "left");
+        assert_eq!(tally.leaders(), ["jump", "left"]);
+        let mut rng = StdRng::seed_from_u64(0);
+        let winners: Vec<String> = (0..50).map(|_| tally.winner(&mut rng).unwrap()).collect();
+        assert!(winners.iter().all(|winner| winner != "right"));
+        assert!(winners.iter().any(|winner| winner == "jump"));
+        assert!(winners.iter().any(|winner| winner == "left"));
+    }
+
+    #[test]
+    fn nobody_wins_without_votes() {
+        let tally = VoteTally::new();
+        assert!(tally.leaders().is_empty());
+        assert_eq!(tally.winner(&mut StdRng::seed_from_u64(0)), None);
+    }
+}